@@ -1,17 +1,51 @@
 use axum::{
     Json, Router,
-    http::{StatusCode, header},
-    response::IntoResponse,
+    extract::{
+        DefaultBodyLimit, FromRequestParts, MatchedPath, Path, Query, Request, State,
+        rejection::{JsonRejection, QueryRejection},
+    },
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header, request::Parts},
+    middleware::{self, Next},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
     routing::{get, post},
 };
 use dotenvy::dotenv;
+use futures::future;
+use futures::stream::{self, StreamExt};
+use metrics::{counter, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use reqwest::cookie::Jar;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::Infallible;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
 use tower::ServiceBuilder;
+use tower_http::compression::{
+    CompressionLayer,
+    predicate::{NotForContentType, Predicate, SizeAbove},
+};
+use tracing::field::Empty;
+use tracing::{Instrument, Span, debug, error, info, info_span, warn};
+use tracing_subscriber::EnvFilter;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use uuid::Uuid;
 use validator::ValidationError;
 use vercel_runtime::Error;
 use vercel_runtime::axum::VercelLayer;
-use yt_transcript_rs::{YouTubeTranscriptApi, proxies::GenericProxyConfig};
+use yt_transcript_rs::{
+    CookieJarLoader, FetchedTranscript, FetchedTranscriptSnippet, YouTubeTranscriptApi,
+    errors::{CouldNotRetrieveTranscript, CouldNotRetrieveTranscriptReason},
+    proxies::{GenericProxyConfig, ProxyConfig},
+};
 use tower_http::cors::{Any, CorsLayer};
 
 async fn favicon() -> impl IntoResponse {
@@ -22,10 +56,25 @@ async fn favicon() -> impl IntoResponse {
 }
 
 fn validate_video_id(video_id: &str) -> Result<(), ValidationError> {
-    if video_id.len() != 11 {
-        return Err(ValidationError::new(
-            "video_id must be exactly 11 characters",
-        ));
+    let len = video_id.chars().count();
+    if len != 11 {
+        let (code, hint) = if len < 11 {
+            ("too_short", "check that the whole ID was copied")
+        } else {
+            (
+                "too_long",
+                "paste only the ID, or send the full link as video_url",
+            )
+        };
+        let mut error = ValidationError::new(code);
+        error.message = Some(
+            format!(
+                "video_id must be exactly 11 characters, got {}; {}",
+                len, hint
+            )
+            .into(),
+        );
+        return Err(error);
     }
 
     let invalid_chars: Vec<char> = video_id
@@ -35,31 +84,156 @@ fn validate_video_id(video_id: &str) -> Result<(), ValidationError> {
 
     if !invalid_chars.is_empty() {
         let mut error = ValidationError::new("invalid_characters");
+        error.message = Some(
+            format!(
+                "video_id contains invalid characters: {:?}; only letters, digits, '-' and '_' are allowed",
+                invalid_chars
+            )
+            .into(),
+        );
+        return Err(error);
+    }
+
+    // Checked ahead of the last character (which also rules this out) so
+    // all-punctuation junk gets the more specific message
+    if !video_id.chars().any(|c| c.is_ascii_alphanumeric()) {
+        let mut error = ValidationError::new("not_an_id");
+        error.message = Some("video_id must contain at least one letter or digit".into());
+        return Err(error);
+    }
+
+    // IDs are 64 bits in 11 base64url characters, so the last one only carries
+    // four bits and is always one of these 16
+    if !video_id.ends_with(|c: char| "AEIMQUYcgkosw048".contains(c)) {
+        let mut error = ValidationError::new("not_an_id");
         error.message =
-            Some(format!("video_id contains invalid characters: {:?}", invalid_chars).into());
+            Some("video_id is not a valid YouTube ID; check that it was copied exactly".into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+fn validate_language_code(code: &str) -> Result<(), ValidationError> {
+    // Loose BCP-47 shape: 2-3 letter primary tag, then 1-8 char alphanumeric subtags
+    let mut parts = code.split('-');
+    let primary = parts.next().unwrap_or_default();
+    let primary_ok =
+        (2..=3).contains(&primary.len()) && primary.chars().all(|c| c.is_ascii_alphabetic());
+    let subtags_ok =
+        parts.all(|p| (1..=8).contains(&p.len()) && p.chars().all(|c| c.is_ascii_alphanumeric()));
+
+    if !primary_ok || !subtags_ok {
+        let mut error = ValidationError::new("invalid_language");
+        error.message = Some(format!("invalid language code: {:?}", code).into());
         return Err(error);
     }
 
     Ok(())
 }
 
-fn format_views(views: &str) -> String {
+fn validate_playlist_id(playlist_id: &str) -> Result<(), ValidationError> {
+    // IDs vary by kind (PL…, UU…, OLAK5uy_…, RD… mixes) but share one alphabet
+    let valid_chars = playlist_id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !(2..=64).contains(&playlist_id.len()) || !valid_chars {
+        let mut error = ValidationError::new("invalid_playlist_id");
+        error.message = Some(format!("invalid playlist ID: {:?}", playlist_id).into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+struct NumberLocale {
+    decimal: char,
+    group: char,
+    // Thousand, million and billion suffixes, including any leading space
+    suffixes: [&'static str; 3],
+}
+
+const EN_LOCALE: NumberLocale = NumberLocale {
+    decimal: '.',
+    group: ',',
+    suffixes: ["K", "M", "B"],
+};
+
+// Matches on the primary subtag only; anything unrecognized formats as English
+fn number_locale(tag: Option<&str>) -> &'static NumberLocale {
+    const DE: NumberLocale = NumberLocale {
+        decimal: ',',
+        group: '.',
+        suffixes: [" Tsd.", " Mio.", " Mrd."],
+    };
+    const FR: NumberLocale = NumberLocale {
+        decimal: ',',
+        // Narrow no-break space, per French typography
+        group: '\u{202F}',
+        suffixes: [" k", " M", " Md"],
+    };
+    const ES: NumberLocale = NumberLocale {
+        decimal: ',',
+        group: '.',
+        suffixes: [" mil", " M", " mil M"],
+    };
+
+    let primary = tag
+        .and_then(|t| t.split(['-', '_']).next())
+        .map(str::to_ascii_lowercase);
+    match primary.as_deref() {
+        Some("de") => &DE,
+        Some("fr") => &FR,
+        Some("es") => &ES,
+        _ => &EN_LOCALE,
+    }
+}
+
+fn group_digits(n: u64, separator: char) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(separator);
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+fn format_views(views: &str, locale: &NumberLocale) -> String {
     let num: u64 = match views.parse() {
         Ok(n) => n,
         Err(_) => return views.to_string(),
     };
 
     let (value, suffix) = match num {
-        n if n >= 1_000_000_000 => (n as f64 / 1_000_000_000.0, "B"),
-        n if n >= 1_000_000 => (n as f64 / 1_000_000.0, "M"),
-        n if n >= 1_000 => (n as f64 / 1_000.0, "K"),
+        n if n >= 1_000_000_000 => (n as f64 / 1_000_000_000.0, locale.suffixes[2]),
+        n if n >= 1_000_000 => (n as f64 / 1_000_000.0, locale.suffixes[1]),
+        n if n >= 1_000 => (n as f64 / 1_000.0, locale.suffixes[0]),
         n => return n.to_string(),
     };
 
     // Format with 1 decimal, then strip ".0" if present
     let formatted = format!("{:.1}", value);
     let clean = formatted.trim_end_matches(".0");
-    format!("{}{}", clean, suffix)
+    let (whole, fraction) = clean.split_once('.').unwrap_or((clean, ""));
+    let whole = whole
+        .parse()
+        .map_or_else(|_| whole.to_string(), |n| group_digits(n, locale.group));
+    if fraction.is_empty() {
+        format!("{}{}", whole, suffix)
+    } else {
+        format!("{}{}{}{}", whole, locale.decimal, fraction, suffix)
+    }
+}
+
+fn format_view_count(count: u64, format: ViewFormat, locale: &NumberLocale) -> String {
+    match format {
+        ViewFormat::Abbreviated => format_views(&count.to_string(), locale),
+        ViewFormat::Full => group_digits(count, locale.group),
+        ViewFormat::Raw => count.to_string(),
+    }
 }
 
 fn seconds_to_timestamp(seconds: f64) -> String {
@@ -75,13 +249,703 @@ fn seconds_to_timestamp(seconds: f64) -> String {
     }
 }
 
+fn format_timestamp(seconds: f64, format: TimestampFormat) -> String {
+    match format {
+        TimestampFormat::Auto => seconds_to_timestamp(seconds),
+        TimestampFormat::AlwaysHours => {
+            let total = seconds as u64;
+            format!(
+                "{:02}:{:02}:{:02}",
+                total / 3600,
+                (total % 3600) / 60,
+                total % 60
+            )
+        }
+        TimestampFormat::Seconds => (seconds as u64).to_string(),
+    }
+}
+
+fn seconds_to_cue_timestamp(seconds: f64, separator: char) -> String {
+    let total_ms = (seconds.max(0.0) * 1000.0).round() as u64;
+    let hours = total_ms / 3_600_000;
+    let mins = (total_ms % 3_600_000) / 60_000;
+    let secs = (total_ms % 60_000) / 1000;
+    let ms = total_ms % 1000;
+
+    format!("{:02}:{:02}:{:02}{}{:03}", hours, mins, secs, separator, ms)
+}
+
+fn to_srt(snippets: &[TranscriptSnippet]) -> String {
+    // Cues are emitted in transcript order, even if they overlap
+    let mut out = String::new();
+    for (i, snippet) in snippets.iter().enumerate() {
+        out.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            i + 1,
+            seconds_to_cue_timestamp(snippet.start_seconds, ','),
+            seconds_to_cue_timestamp(snippet.start_seconds + snippet.duration, ','),
+            snippet.text
+        ));
+    }
+    out
+}
+
+fn to_vtt(snippets: &[TranscriptSnippet]) -> String {
+    let mut out = String::from("WEBVTT\n\n");
+    for snippet in snippets {
+        out.push_str(&format!(
+            "{} --> {}\n{}\n\n",
+            seconds_to_cue_timestamp(snippet.start_seconds, '.'),
+            seconds_to_cue_timestamp(snippet.start_seconds + snippet.duration, '.'),
+            snippet.text
+        ));
+    }
+    out
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn to_csv(snippets: &[TranscriptSnippet]) -> String {
+    // RFC 4180: CRLF line endings, fields quoted only when needed
+    let mut out = String::from("start,end,duration,text\r\n");
+    for snippet in snippets {
+        out.push_str(&format!(
+            "{},{},{},{}\r\n",
+            csv_escape(&snippet.start),
+            csv_escape(&snippet.end),
+            snippet.duration,
+            csv_escape(&snippet.text)
+        ));
+    }
+    out
+}
+
+fn to_jsonl(snippets: &[TranscriptSnippet]) -> String {
+    // Every record, including the last, ends in "\n"; no snippets is an empty body
+    snippets
+        .iter()
+        .map(|snippet| serde_json::to_string(snippet).unwrap_or_default() + "\n")
+        .collect()
+}
+
+fn markdown_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '~') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn to_markdown(video_id: &str, snippets: &[TranscriptSnippet]) -> String {
+    snippets
+        .iter()
+        .map(|snippet| {
+            format!(
+                "[{}](https://youtu.be/{}?t={}) {}\n",
+                snippet.start,
+                video_id,
+                snippet.start_seconds as u64,
+                markdown_escape(&snippet.text)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Escapes the characters that matter in both text and attribute positions
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// A self-contained fragment for embedding: one list item per caption, each
+// timestamp deep-linking into the video
+fn to_html(video_id: &str, snippets: &[TranscriptSnippet]) -> String {
+    let mut out = String::from("<ol class=\"transcript\">\n");
+    for snippet in snippets {
+        out.push_str(&format!(
+            "<li><a href=\"https://youtu.be/{}?t={}\">{}</a> {}</li>\n",
+            html_escape(video_id),
+            snippet.start_seconds as u64,
+            html_escape(&snippet.start),
+            html_escape(&snippet.text)
+        ));
+    }
+    out.push_str("</ol>\n");
+    out
+}
+
+fn to_plain_text(snippets: &[TranscriptSnippet], separator: &str) -> String {
+    snippets
+        .iter()
+        .map(|snippet| {
+            snippet
+                .text
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn query_param<'a>(url: &'a str, key: &str) -> Option<&'a str> {
+    let query = url.split_once('?')?.1;
+    let query = query.split('#').next().unwrap_or(query);
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+}
+
+// Parses a `t=` value: raw seconds ("90", "90s") or "1h2m3s" style
+fn parse_time_param(value: &str) -> Option<u64> {
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(secs);
+    }
+
+    let mut total: u64 = 0;
+    let mut digits = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let n: u64 = digits.parse().ok()?;
+        digits.clear();
+        let unit = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        // `t=` comes straight from the client; an absurd value is no value
+        total = total.checked_add(n.checked_mul(unit)?)?;
+    }
+
+    if !digits.is_empty() {
+        return None;
+    }
+    Some(total)
+}
+
+// Parses a description timestamp: "M:SS", "MM:SS" or "H:MM:SS"
+fn parse_clock_timestamp(value: &str) -> Option<u64> {
+    let parts: Vec<&str> = value.split(':').collect();
+    if !(2..=3).contains(&parts.len())
+        || parts
+            .iter()
+            .any(|p| p.is_empty() || !p.bytes().all(|b| b.is_ascii_digit()))
+    {
+        return None;
+    }
+
+    let mut total: u64 = 0;
+    for (i, part) in parts.iter().enumerate() {
+        let n: u64 = part.parse().ok()?;
+        // Everything after the leading field is base 60 and two digits wide
+        if i > 0 && (n >= 60 || part.len() != 2) {
+            return None;
+        }
+        total = total.checked_mul(60)?.checked_add(n)?;
+    }
+    Some(total)
+}
+
+// Chapters are lines like "00:00 Intro" or "1:02:03 - Q&A". Following YouTube's
+// own rules, the list only counts if it starts at 0:00 and has three entries
+fn parse_chapters(description: &str) -> Vec<Chapter> {
+    let mut chapters: Vec<Chapter> = Vec::new();
+    for line in description.lines() {
+        let line = line.trim_start_matches(|c: char| c.is_whitespace() || "-•*([".contains(c));
+        let (stamp, title) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let Some(start_seconds) = parse_clock_timestamp(stamp.trim_end_matches([')', ']'])) else {
+            continue;
+        };
+        let title = title
+            .trim_start_matches(|c: char| c.is_whitespace() || "-–—:|".contains(c))
+            .trim();
+        if title.is_empty() {
+            continue;
+        }
+        // Timestamps must ascend; a line going backwards isn't part of the list
+        if chapters
+            .last()
+            .is_some_and(|c| start_seconds <= c.start_seconds)
+        {
+            continue;
+        }
+        chapters.push(Chapter {
+            start_seconds,
+            title: title.to_string(),
+        });
+    }
+
+    if chapters.len() < 3 || chapters[0].start_seconds != 0 {
+        return Vec::new();
+    }
+    chapters
+}
+
+// WPM uses the spoken span (first start to last end) so intros and outros
+// without captions don't drag it down
+fn transcript_stats(snippets: &[TranscriptSnippet]) -> TranscriptStats {
+    let word_count = snippets
+        .iter()
+        .map(|s| s.text.split_whitespace().count())
+        .sum();
+    let spoken_seconds = match (snippets.first(), snippets.last()) {
+        (Some(first), Some(last)) => {
+            (last.start_seconds + last.duration - first.start_seconds).max(0.0)
+        }
+        _ => 0.0,
+    };
+    let words_per_minute = if spoken_seconds > 0.0 {
+        (word_count as f64 / (spoken_seconds / 60.0) * 10.0).round() / 10.0
+    } else {
+        0.0
+    };
+
+    TranscriptStats {
+        word_count,
+        snippet_count: snippets.len(),
+        spoken_seconds,
+        words_per_minute,
+    }
+}
+
+// `chapters` is sorted by start, so each lookup is a binary search
+fn assign_chapters(snippets: &mut [TranscriptSnippet], chapters: &[Chapter]) {
+    for snippet in snippets {
+        let index = chapters.partition_point(|c| c.start_seconds as f64 <= snippet.start_seconds);
+        snippet.chapter = index.checked_sub(1).map(|i| chapters[i].title.clone());
+    }
+}
+
+fn extract_start_from_url(url: &str) -> Option<u64> {
+    let url = url.trim();
+    query_param(url, "t")
+        .or_else(|| query_param(url, "start"))
+        .and_then(parse_time_param)
+}
+
+fn filter_time_range(
+    snippets: &mut Vec<TranscriptSnippet>,
+    start_after: Option<f64>,
+    end_before: Option<f64>,
+) {
+    // Keep any snippet that overlaps the range, including ones straddling a boundary
+    snippets.retain(|snippet| {
+        let end = snippet.start_seconds + snippet.duration;
+        start_after.is_none_or(|after| end > after)
+            && end_before.is_none_or(|before| snippet.start_seconds < before)
+    });
+}
+
+// Single pass, so "&amp;lt;" decodes to "&lt;" rather than "<"
+fn decode_html_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let entity = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .map(|end| &rest[1..=end]);
+        let ch = entity.and_then(|name| match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => {
+                let code = name.strip_prefix('#')?;
+                let value = match code.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => code.parse().ok()?,
+                };
+                char::from_u32(value)
+            }
+        });
+
+        match (ch, entity) {
+            (Some(ch), Some(name)) => {
+                decoded.push(ch);
+                rest = &rest[name.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn clean_text(text: &str) -> String {
+    decode_html_entities(text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Drops sound annotations like "[Music]" or "(applause)", nesting included
+fn strip_bracketed(text: &str) -> String {
+    extract_bracketed(text).0
+}
+
+// Longer bracketed text is more likely a spoken aside than a sound cue
+const MAX_SOUND_CUE_WORDS: usize = 3;
+
+// Splits a caption into its spoken text and the outermost sound cues, with
+// brackets removed. Bracketed asides longer than MAX_SOUND_CUE_WORDS, and
+// anything left unclosed, stay in the spoken text as written
+fn extract_bracketed(text: &str) -> (String, Vec<String>) {
+    let mut kept = String::with_capacity(text.len());
+    let mut annotations = Vec::new();
+    // The outermost open group, brackets included
+    let mut group = String::new();
+    let mut depth = 0usize;
+    for c in text.chars() {
+        match c {
+            '[' | '(' => {
+                group.push(c);
+                depth += 1;
+            }
+            ']' | ')' if depth > 0 => {
+                group.push(c);
+                depth -= 1;
+                if depth == 0 {
+                    let inner = &group[1..group.len() - 1];
+                    let words: Vec<&str> = inner.split_whitespace().collect();
+                    if words.len() > MAX_SOUND_CUE_WORDS {
+                        kept.push_str(&group);
+                    } else if !words.is_empty() {
+                        annotations.push(words.join(" "));
+                    }
+                    group.clear();
+                }
+            }
+            _ if depth == 0 => kept.push(c),
+            _ => group.push(c),
+        }
+    }
+    kept.push_str(&group);
+    let kept = kept.split_whitespace().collect::<Vec<_>>().join(" ");
+    (kept, annotations)
+}
+
+// "no" leads "no more" but not "nothing else", which is a different word
+fn is_word_prefix(prefix: &str, text: &str) -> bool {
+    text.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+// Rolling ASR captions repeat the previous line with a few words appended;
+// collapse each run into its longest variant spanning the whole run
+fn dedup_rolling(snippets: &mut Vec<TranscriptSnippet>) {
+    let mut deduped: Vec<TranscriptSnippet> = Vec::with_capacity(snippets.len());
+    for snippet in snippets.drain(..) {
+        if let Some(prev) = deduped.last_mut() {
+            let (prev_text, text) = (prev.text.trim(), snippet.text.trim());
+            // An empty side would prefix-match anything
+            if !text.is_empty()
+                && !prev_text.is_empty()
+                && (is_word_prefix(prev_text, text) || is_word_prefix(text, prev_text))
+            {
+                if text.len() > prev_text.len() {
+                    prev.text = snippet.text;
+                }
+                let end = (prev.start_seconds + prev.duration)
+                    .max(snippet.start_seconds + snippet.duration);
+                prev.duration = end - prev.start_seconds;
+                prev.end = seconds_to_timestamp(end);
+                prev.speaker_change |= snippet.speaker_change;
+                continue;
+            }
+        }
+        deduped.push(snippet);
+    }
+    *snippets = deduped;
+}
+
+// Masks whole words only, so "Scunthorpe" or "shell" survive; `wordlist` is
+// expected to be lowercase
+fn mask_profanity(text: &str, wordlist: &[String]) -> String {
+    let mut masked = String::with_capacity(text.len());
+    let mut word_start = None;
+    let flush = |masked: &mut String, word: &str| {
+        if wordlist.contains(&word.to_lowercase()) {
+            masked.extend(std::iter::repeat_n('*', word.chars().count()));
+        } else {
+            masked.push_str(word);
+        }
+    };
+
+    for (i, c) in text.char_indices() {
+        let is_word = c.is_alphanumeric() || c == '\'';
+        match (is_word, word_start) {
+            (true, None) => word_start = Some(i),
+            (false, Some(start)) => {
+                flush(&mut masked, &text[start..i]);
+                word_start = None;
+                masked.push(c);
+            }
+            (false, None) => masked.push(c),
+            (true, Some(_)) => {}
+        }
+    }
+    if let Some(start) = word_start {
+        flush(&mut masked, &text[start..]);
+    }
+    masked
+}
+
+// Re-segments captions so each entry ends at `.`, `?` or `!`. A sentence starts
+// at the start of the snippet its first word came from and ends with the
+// snippet holding its last word
+fn to_sentences(snippets: &[TranscriptSnippet]) -> Vec<TranscriptSnippet> {
+    let mut sentences: Vec<TranscriptSnippet> = Vec::new();
+    let mut current: Option<TranscriptSnippet> = None;
+
+    for snippet in snippets {
+        let end = snippet.start_seconds + snippet.duration;
+        for word in snippet.text.split_whitespace() {
+            let sentence = current.get_or_insert_with(|| TranscriptSnippet {
+                start: snippet.start.clone(),
+                start_seconds: snippet.start_seconds,
+                end: String::new(),
+                duration: 0.0,
+                text: String::new(),
+                chapter: None,
+                meta: None,
+                link: None,
+                index: None,
+                speaker_change: snippet.speaker_change,
+                position: snippet.position,
+            });
+            if !sentence.text.is_empty() {
+                sentence.text.push(' ');
+            }
+            sentence.text.push_str(word);
+            sentence.duration = end - sentence.start_seconds;
+            sentence.end = snippet.end.clone();
+
+            let trimmed = word.trim_end_matches(['"', '\'', ')', ']']);
+            if trimmed.ends_with(['.', '?', '!']) {
+                sentences.extend(current.take());
+            }
+        }
+    }
+
+    sentences.extend(current);
+    sentences
+}
+
+fn to_snippet(position: usize, part: &FetchedTranscriptSnippet) -> TranscriptSnippet {
+    // `>>` only marks a speaker change at the start of a caption; anywhere
+    // else it's left as part of the text
+    let marker = part.text.trim_start().strip_prefix(">>");
+    TranscriptSnippet {
+        start: seconds_to_timestamp(part.start),
+        start_seconds: part.start,
+        end: seconds_to_timestamp(part.start + part.duration),
+        duration: part.duration,
+        text: marker
+            .map_or(part.text.as_str(), str::trim_start)
+            .to_string(),
+        chapter: None,
+        meta: None,
+        link: None,
+        index: None,
+        speaker_change: marker.is_some(),
+        position,
+    }
+}
+
+// Pads each (start, end) span by `padding` seconds and merges any that then
+// overlap; windows that only touch stay separate. `spans` must be sorted by
+// start; so is the result
+fn merge_windows(spans: &[(f64, f64)], padding: f64) -> Vec<(f64, f64)> {
+    let mut merged: Vec<(f64, f64)> = Vec::with_capacity(spans.len());
+    for &(start, end) in spans {
+        let (start, end) = ((start - padding).max(0.0), end + padding);
+        match merged.last_mut() {
+            Some(last) if start < last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+fn matches_query(text: &str, query: &str, whole_word: bool) -> bool {
+    // `query` is expected to already be lowercased
+    let text = text.to_lowercase();
+    if !whole_word {
+        return text.contains(query);
+    }
+
+    let is_word_char = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+    text.match_indices(query).any(|(i, m)| {
+        !is_word_char(text[..i].chars().next_back())
+            && !is_word_char(text[i + m.len()..].chars().next())
+    })
+}
+
+// Trailing text of a finished chunk to repeat at the start of the next one:
+// whole sentences if any fit within `overlap` chars, otherwise whole words
+fn overlap_tail(text: &str, overlap: usize) -> String {
+    if overlap == 0 {
+        return String::new();
+    }
+
+    let sentences: Vec<&str> = text
+        .split_inclusive(['.', '?', '!'])
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+    let units: Vec<&str> = if sentences.len() > 1 {
+        sentences
+    } else {
+        text.split_whitespace().collect()
+    };
+
+    let mut tail: Vec<&str> = Vec::new();
+    let mut len = 0;
+    for unit in units.iter().rev() {
+        let added = unit.chars().count() + usize::from(!tail.is_empty());
+        if len + added > overlap {
+            break;
+        }
+        len += added;
+        tail.push(unit);
+    }
+    tail.reverse();
+    tail.join(" ")
+}
+
+fn to_chunks(
+    snippets: &[TranscriptSnippet],
+    max_chars: usize,
+    overlap: usize,
+) -> Vec<TranscriptChunk> {
+    let mut chunks = Vec::new();
+    let mut current: Option<TranscriptChunk> = None;
+    // Length of the carried-over overlap at the head of `current`
+    let mut carried = 0;
+
+    for snippet in snippets {
+        let text = snippet
+            .text
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if text.is_empty() {
+            continue;
+        }
+
+        if let Some(chunk) = &current {
+            let len = chunk.text.chars().count();
+            if len > carried && len + 1 + text.chars().count() > max_chars {
+                let finished = current.take().unwrap();
+                let tail = overlap_tail(&finished.text, overlap);
+                carried = tail.chars().count();
+                chunks.push(finished);
+                if !tail.is_empty() {
+                    current = Some(TranscriptChunk {
+                        start: snippet.start.clone(),
+                        text: tail,
+                    });
+                }
+            }
+        }
+
+        match &mut current {
+            Some(chunk) => {
+                chunk.text.push(' ');
+                chunk.text.push_str(&text);
+            }
+            None => {
+                carried = 0;
+                current = Some(TranscriptChunk {
+                    start: snippet.start.clone(),
+                    text,
+                });
+            }
+        }
+    }
+
+    chunks.extend(current);
+    chunks
+}
+
+// Starts a new turn at every speaker-change marker; text before the first
+// marker is turn 0
+fn to_speaker_turns(snippets: &[TranscriptSnippet]) -> Vec<SpeakerTurn> {
+    let mut turns: Vec<SpeakerTurn> = Vec::new();
+    for snippet in snippets {
+        let text = snippet
+            .text
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if text.is_empty() {
+            continue;
+        }
+
+        match turns.last_mut() {
+            Some(turn) if !snippet.speaker_change => {
+                turn.text.push(' ');
+                turn.text.push_str(&text);
+                turn.end = snippet.end.clone();
+            }
+            last => {
+                let speaker_index = last.map_or(0, |turn| turn.speaker_index + 1);
+                turns.push(SpeakerTurn {
+                    speaker_index,
+                    start: snippet.start.clone(),
+                    end: snippet.end.clone(),
+                    text,
+                });
+            }
+        }
+    }
+    turns
+}
+
 #[allow(clippy::collapsible_if)]
 fn extract_id_from_url(url: &str) -> Result<String, ValidationError> {
     let url = url.trim();
 
     // Full URL: https://www.youtube.com/watch?v=VIDEO_ID
+    // (also m.youtube.com and music.youtube.com, with v= anywhere in the query)
     if url.contains("youtube.com/watch") {
-        if let Some(id) = url.split("v=").nth(1).and_then(|s| s.split('&').next()) {
+        if let Some(id) = query_param(url, "v") {
             if validate_video_id(id).is_ok() {
                 return Ok(id.to_string());
             }
@@ -107,156 +971,4609 @@ fn extract_id_from_url(url: &str) -> Result<String, ValidationError> {
         ));
     }
 
+    // Shorts URL: https://www.youtube.com/shorts/VIDEO_ID
+    if url.contains("youtube.com/shorts/") {
+        if let Some(id) = url
+            .split("/shorts/")
+            .nth(1)
+            .and_then(|s| s.split(['?', '/']).next())
+        {
+            if validate_video_id(id).is_ok() {
+                return Ok(id.to_string());
+            }
+        }
+        return Err(ValidationError::new(
+            "invalid YouTube URL: could not extract valid video ID",
+        ));
+    }
+
+    // Embed URL: https://www.youtube.com/embed/VIDEO_ID
+    if url.contains("youtube.com/embed/") {
+        if let Some(id) = url
+            .split("/embed/")
+            .nth(1)
+            .and_then(|s| s.split(['?', '&', '/']).next())
+        {
+            if validate_video_id(id).is_ok() {
+                return Ok(id.to_string());
+            }
+        }
+        return Err(ValidationError::new(
+            "invalid YouTube URL: could not extract valid video ID",
+        ));
+    }
+
     Err(ValidationError::new(
-        "invalid YouTube URL: must be youtube.com/watch or youtu.be URL",
+        "invalid YouTube URL: must be youtube.com/watch, youtube.com/shorts, youtube.com/embed or youtu.be URL",
     ))
 }
 
-#[derive(Deserialize)]
-struct YTRequest {
-    video_id: Option<String>,
-    video_url: Option<String>,
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    #[default]
+    Json,
+    Srt,
+    Vtt,
+    Text,
+    Csv,
+    #[serde(alias = "md")]
+    Markdown,
+    #[serde(alias = "ndjson")]
+    Jsonl,
+    Html,
 }
 
-#[derive(Serialize)]
-struct YTResponse {
+impl OutputFormat {
+    const ALL: [Self; 8] = [
+        Self::Json,
+        Self::Srt,
+        Self::Vtt,
+        Self::Text,
+        Self::Csv,
+        Self::Markdown,
+        Self::Jsonl,
+        Self::Html,
+    ];
+
+    // The canonical name accepted in `format`
+    fn name(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Srt => "srt",
+            Self::Vtt => "vtt",
+            Self::Text => "text",
+            Self::Csv => "csv",
+            Self::Markdown => "markdown",
+            Self::Jsonl => "jsonl",
+            Self::Html => "html",
+        }
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "application/json" => Some(Self::Json),
+            "application/x-subrip" | "text/srt" => Some(Self::Srt),
+            "text/vtt" => Some(Self::Vtt),
+            "text/plain" => Some(Self::Text),
+            "text/csv" => Some(Self::Csv),
+            "text/markdown" => Some(Self::Markdown),
+            "application/x-ndjson" | "application/jsonl" => Some(Self::Jsonl),
+            // text/html is left out on purpose: every browser sends it, and
+            // they should keep getting JSON unless `format` asks for html
+            _ => None,
+        }
+    }
+}
+
+// The output format named by the Accept header: the highest-q media type we
+// can produce, or None for `*/*`, a missing header or nothing recognized
+struct AcceptFormat(Option<OutputFormat>);
+
+impl<S: Send + Sync> FromRequestParts<S> for AcceptFormat {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let accept = parts
+            .headers
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+
+        let mut best: Option<(f32, OutputFormat)> = None;
+        for range in accept.split(',') {
+            let mut params = range.split(';');
+            let media_type = params
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase();
+            let q = params
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            if let Some(format) = OutputFormat::from_media_type(&media_type)
+                && q > 0.0
+                && best.is_none_or(|(best_q, _)| q > best_q)
+            {
+                best = Some((q, format));
+            }
+        }
+        Ok(Self(best.map(|(_, format)| format)))
+    }
+}
+
+// Languages from the Accept-Language header, highest q first, normalized like
+// the body's `language` field. Wildcards and malformed tags are skipped
+struct AcceptLanguage(Vec<String>);
+
+impl<S: Send + Sync> FromRequestParts<S> for AcceptLanguage {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let header = parts
+            .headers
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+
+        let mut ranked: Vec<(f32, String)> = header
+            .split(',')
+            .filter_map(|range| {
+                let mut params = range.split(';');
+                let tag = params.next().unwrap_or_default().trim();
+                let q = params
+                    .filter_map(|p| p.trim().strip_prefix("q="))
+                    .find_map(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                let code = normalize_language(tag);
+                (q > 0.0 && validate_language_code(&code).is_ok()).then_some((q, code))
+            })
+            .collect();
+        // Stable, so equal weights keep the order the client sent them in
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut languages: Vec<String> = Vec::new();
+        for (_, code) in ranked {
+            if !languages.contains(&code) {
+                languages.push(code);
+            }
+        }
+        Ok(Self(languages))
+    }
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum TimestampFormat {
+    #[default]
+    Auto,
+    AlwaysHours,
+    Seconds,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum TextCase {
+    #[default]
+    Original,
+    Lower,
+    Upper,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum ViewFormat {
+    #[default]
+    Abbreviated,
+    Full,
+    Raw,
+}
+
+#[derive(Deserialize, Default, ToSchema)]
+struct YTRequest {
+    video_id: Option<String>,
+    video_url: Option<String>,
+    // Takes precedence over the Accept header; JSON when neither picks one
+    format: Option<OutputFormat>,
+    #[serde(default)]
+    line_breaks: bool,
+    // Between captions in plain text and `full_text`; overrides line_breaks
+    join_separator: Option<String>,
+    language: Option<String>,
+    languages: Option<Vec<String>>,
+    translate_to: Option<String>,
+    start_after: Option<f64>,
+    end_before: Option<f64>,
+    search: Option<String>,
+    #[serde(default)]
+    whole_word: bool,
+    // Caps search matches; DEFAULT_MAX_MATCHES when unset
+    max_matches: Option<usize>,
+    // Also keeps snippets within this many seconds of each match
+    context_seconds: Option<f64>,
+    chunk: Option<ChunkOptions>,
+    #[serde(default)]
+    timestamp_format: TimestampFormat,
+    #[serde(default)]
+    include_full_text: bool,
+    #[serde(default)]
+    dedup: bool,
+    locale: Option<String>,
+    #[serde(default)]
+    view_format: ViewFormat,
+    #[serde(default)]
+    group_speakers: bool,
+    #[serde(default)]
+    include_chapters: bool,
+    #[serde(default)]
+    include_stats: bool,
+    #[serde(default)]
+    detect_language: bool,
+    // Adds a `meta` object to each snippet
+    #[serde(default)]
+    include_meta: bool,
+    // Adds a `link` to each snippet's start
+    #[serde(default)]
+    include_links: bool,
+    // Adds each snippet's `index` in the unfiltered transcript
+    #[serde(default)]
+    include_index: bool,
+    #[serde(default)]
+    mask_profanity: bool,
+    #[serde(default)]
+    text_case: TextCase,
+    // Indented JSON for reading by hand; compact by default to save bandwidth
+    #[serde(default)]
+    pretty: bool,
+    // Decode HTML entities and collapse whitespace; on unless explicitly disabled
+    clean: Option<bool>,
+    // Prefer a human-authored track over auto-generated ones; defaults to true
+    prefer_manual: Option<bool>,
+    #[serde(default)]
+    strip_brackets: bool,
+    // Moves bracketed cues into a separate `sounds` list
+    #[serde(default)]
+    extract_sounds: bool,
+    #[serde(default)]
+    sentences: bool,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    preview_seconds: Option<f64>,
+    // Fetch every entry of `languages` side by side rather than as fallbacks
+    #[serde(default)]
+    multi: bool,
+    // `false` goes direct even when proxies are configured
+    use_proxy: Option<bool>,
+    // Skips the MAX_VIDEO_DURATION_SECS guard
+    #[serde(default)]
+    allow_long: bool,
+    // `false` returns the transcript even if title/author/views can't be
+    // fetched; defaults to true
+    require_details: Option<bool>,
+    // From Accept-Language; tried ahead of the defaults when neither
+    // `language` nor `languages` is given
+    #[serde(skip)]
+    accept_languages: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct ChunkOptions {
+    max_chars: usize,
+    #[serde(default)]
+    overlap: usize,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+struct YTResponse {
     id: String,
+    // The video_id or video_url exactly as the client sent it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    canonical_url: Option<String>,
     title: String,
     author: String,
     views: String,
+    view_count: Option<u64>,
+    published_at: Option<String>,
+    duration_seconds: Option<u64>,
+    duration: Option<String>,
+    start_seconds: Option<u64>,
+    thumbnail_url: String,
+    thumbnails: Vec<Thumbnail>,
+    // Code of the track actually returned, after fallback and translation
+    language: String,
+    is_translated: bool,
+    generated: bool,
+    quality_hint: QualityHint,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    full_text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chapters: Option<Vec<Chapter>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<TranscriptStats>,
+    // ISO 639-3 code (e.g. "eng") guessed from the text itself by
+    // `detect_language`, whatever the track is labeled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detected_language: Option<String>,
+    // 0.0-1.0; only set alongside `detected_language`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detected_language_confidence: Option<f64>,
+    // Bracketed cues pulled out of the text by `extract_sounds`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sounds: Option<Vec<SoundCue>>,
+    // Text transforms applied to this response, in order; empty when the
+    // text is YouTube's verbatim
+    transformations: Vec<&'static str>,
+    // Set when `require_details: false` let a details failure through, leaving
+    // title, author and views empty
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    details_unavailable: bool,
+    // Only set for paginated requests; `total` counts snippets before slicing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    has_more: Option<bool>,
+    // Only set for preview and search requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    truncated: Option<bool>,
+    // Matches before `max_matches` was applied; only set for search requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_matches: Option<usize>,
     transcript: Vec<TranscriptSnippet>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
+struct VideoDetailsResponse {
+    id: String,
+    title: String,
+    author: String,
+    views: String,
+    view_count: Option<u64>,
+    published_at: Option<String>,
+    duration_seconds: Option<u64>,
+    duration: Option<String>,
+    thumbnail_url: String,
+    thumbnails: Vec<Thumbnail>,
+    #[serde(skip)]
+    description: String,
+    #[serde(skip)]
+    tags: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct VideoMetaResponse {
+    #[serde(flatten)]
+    details: VideoDetailsResponse,
+    // Empty rather than missing when the uploader left them blank
+    description: String,
+    tags: Vec<String>,
+}
+
+impl From<VideoDetailsResponse> for VideoMetaResponse {
+    fn from(mut details: VideoDetailsResponse) -> Self {
+        Self {
+            description: std::mem::take(&mut details.description),
+            tags: std::mem::take(&mut details.tags),
+            details,
+        }
+    }
+}
+
+// Trust signal for downstream routing; machine translations of ASR output
+// compound both sets of errors and are worth a human look
+#[derive(Serialize, Clone, Copy, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum QualityHint {
+    Manual,
+    AutoGenerated,
+    Translated,
+    AutoGeneratedTranslated,
+}
+
+impl QualityHint {
+    fn new(generated: bool, translated: bool) -> Self {
+        match (generated, translated) {
+            (false, false) => Self::Manual,
+            (true, false) => Self::AutoGenerated,
+            (false, true) => Self::Translated,
+            (true, true) => Self::AutoGeneratedTranslated,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+struct TranscriptStats {
+    word_count: usize,
+    snippet_count: usize,
+    spoken_seconds: f64,
+    words_per_minute: f64,
+}
+
+// Timed like the snippet it came from
+#[derive(Serialize, Clone, ToSchema)]
+struct SoundCue {
+    start: String,
+    start_seconds: f64,
+    text: String,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+struct Chapter {
+    start_seconds: u64,
+    title: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ChunkedResponse {
+    id: String,
+    title: String,
+    author: String,
+    views: String,
+    // As in YTResponse, applied before chunking
+    transformations: Vec<&'static str>,
+    chunks: Vec<TranscriptChunk>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct TranscriptChunk {
+    start: String,
+    text: String,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+struct Thumbnail {
+    url: String,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
 struct TranscriptSnippet {
     start: String,
+    start_seconds: f64,
+    end: String,
     duration: f64,
     text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chapter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    meta: Option<SnippetMeta>,
+    // Deep link to the snippet's start, added by `include_links`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    link: Option<String>,
+    // Position in the fetched transcript, added by `include_index`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index: Option<usize>,
+    // Set when the raw caption carried a `>> ` speaker-change marker
+    #[serde(skip)]
+    speaker_change: bool,
+    // Assigned at fetch time so it survives filtering and pagination
+    #[serde(skip)]
+    position: usize,
+}
+
+// What the library tells us beyond timing and text. It exposes no per-snippet
+// confidence or on-screen position, so there are no fields for them
+#[derive(Serialize, Clone, ToSchema)]
+struct SnippetMeta {
+    track: TrackKind,
+    speaker_change: bool,
+}
+
+#[derive(Serialize, Clone, Copy, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum TrackKind {
+    Manual,
+    AutoGenerated,
+}
+
+#[derive(Serialize, ToSchema)]
+struct MultiLanguageResponse {
+    id: String,
+    title: String,
+    author: String,
+    views: String,
+    // Keyed by the requested code
+    transcripts: BTreeMap<String, Vec<TranscriptSnippet>>,
+    // Per language, as in YTResponse; same keys as `transcripts`
+    transformations: BTreeMap<String, Vec<&'static str>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    errors: BTreeMap<String, LanguageError>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct LanguageError {
+    error: String,
+    code: &'static str,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SpeakerResponse {
+    id: String,
+    title: String,
+    author: String,
+    views: String,
+    // As in YTResponse, applied before grouping
+    transformations: Vec<&'static str>,
+    turns: Vec<SpeakerTurn>,
+}
+
+// `speaker_index` counts turns; YouTube marks changes, not who is speaking
+#[derive(Serialize, ToSchema)]
+struct SpeakerTurn {
+    speaker_index: usize,
+    start: String,
+    end: String,
+    text: String,
+}
+
+// GET /ready: unlike /health, 503 until the instance has finished warming up.
+// While draining, the in-flight middleware already answers 503
+#[utoipa::path(
+    get,
+    path = "/ready",
+    responses(
+        (status = 200, description = "Initialized and accepting traffic"),
+        (status = 503, description = "Still starting up, or draining for shutdown")
+    )
+)]
+async fn ready(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    if state.ready.load(Ordering::Acquire) {
+        (StatusCode::OK, Json(json!({ "status": "ready" })))
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "status": "starting" })),
+        )
+    }
 }
 
 async fn hello() -> impl IntoResponse {
     Json(json!({ "message": "Welcome to v1-caption!" }))
 }
 
-async fn yt(Json(payload): Json<YTRequest>) -> Result<Json<YTResponse>, (StatusCode, String)> {
-    // Validate: exactly one of video_id or video_url must be provided
-    let video_id = match (&payload.video_id, &payload.video_url) {
-        (Some(_), Some(_)) => {
-            return Err((
-                StatusCode::BAD_REQUEST,
-                "Cannot provide both video_id and video_url. Use one or the other.".to_string(),
-            ));
-        }
-        (None, None) => {
-            return Err((
-                StatusCode::BAD_REQUEST,
-                "Must provide either video_id or video_url.".to_string(),
-            ));
+#[derive(Deserialize, IntoParams)]
+struct HealthQuery {
+    #[serde(default)]
+    deep: bool,
+}
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    params(HealthQuery),
+    responses(
+        (status = 200, description = "Service is up"),
+        (status = 401, description = "Deep check without a valid API key", body = ErrorBody),
+        (status = 503, description = "YouTube unreachable on a deep check")
+    )
+)]
+async fn health(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HealthQuery>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    let proxy_configured = state.proxies.len() > 0;
+
+    // Deep check: make sure YouTube is reachable through the configured client.
+    // It makes an outbound request, so unlike the shallow check it needs a key
+    let mut youtube_reachable = None;
+    if query.deep {
+        check_api_key(&state, &headers)?;
+        let client = state.proxies.client(state.proxies.next());
+        let reachable = client
+            .http
+            .head("https://www.youtube.com")
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+            .is_ok_and(|res| !res.status().is_server_error());
+        youtube_reachable = Some(reachable);
+    }
+
+    let degraded = youtube_reachable == Some(false);
+    let status = if degraded {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+
+    let mut body = json!({
+        "status": if degraded { "degraded" } else { "ok" },
+        "proxy_configured": proxy_configured,
+        "version": env!("CARGO_PKG_VERSION"),
+    });
+    if let Some(reachable) = youtube_reachable {
+        body["youtube_reachable"] = json!(reachable);
+    }
+
+    Ok((status, Json(body)))
+}
+
+struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
         }
+    }
+}
+
+// The JSON body of every error response
+#[derive(Serialize, ToSchema)]
+struct ErrorBody {
+    error: String,
+    code: &'static str,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            error: self.message,
+            code: self.code,
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "v1-caption API"),
+    paths(
+        yt,
+        yt_get_by_url,
+        yt_get,
+        yt_stream,
+        batch,
+        diff,
+        languages,
+        playlist,
+        validate,
+        video,
+        video_get,
+        video_meta,
+        video_meta_get,
+        health,
+        ready
+    ),
+    // The JSON shapes `chunk`, `multi` and `group_speakers` return instead of
+    // YTResponse
+    components(schemas(ChunkedResponse, MultiLanguageResponse, SpeakerResponse))
+)]
+struct ApiDoc;
+
+// GET /openapi.json: generated from the handler annotations and schema derives
+async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+// Plenty for a full batch of URLs; anything bigger is a mistake or abuse
+const MAX_BODY_BYTES: usize = 16 * 1024;
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        // axum reports type mismatches as 422; keep that status for our own
+        // semantic checks so any body that fails to deserialize is a 400
+        let status = match rejection.status() {
+            StatusCode::UNPROCESSABLE_ENTITY => StatusCode::BAD_REQUEST,
+            StatusCode::PAYLOAD_TOO_LARGE => {
+                return Self::new(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    "payload_too_large",
+                    format!("Request body must not exceed {} bytes.", MAX_BODY_BYTES),
+                );
+            }
+            status => status,
+        };
+        Self::new(status, "invalid_body", rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::new(rejection.status(), "invalid_query", rejection.body_text())
+    }
+}
+
+fn validation_error(code: &'static str) -> impl Fn(ValidationError) -> ApiError {
+    move |e| {
+        ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            code,
+            e.message
+                .map(|m| m.to_string())
+                .unwrap_or_else(|| e.code.to_string()),
+        )
+    }
+}
+
+fn resolve_video_id(video_id: Option<&str>, video_url: Option<&str>) -> Result<String, ApiError> {
+    // Validate: exactly one of video_id or video_url must be provided
+    match (video_id, video_url) {
+        (Some(_), Some(_)) => Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "conflicting_video_input",
+            "Cannot provide both video_id and video_url. Use one or the other.",
+        )),
+        (None, None) => Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "missing_video_input",
+            "Must provide either video_id or video_url.",
+        )),
         (Some(id), None) => {
+            // Forgive copy-paste leftovers: surrounding whitespace and a `#t=10` style fragment
+            let id = id.trim();
+            let id = id.split('#').next().unwrap_or(id).trim_end();
             // Validate the video_id
-            validate_video_id(id).map_err(|e| {
-                (
-                    StatusCode::BAD_REQUEST,
-                    e.message
-                        .map(|m| m.to_string())
-                        .unwrap_or_else(|| e.code.to_string()),
-                )
-            })?;
-            id.clone()
+            validate_video_id(id).map_err(validation_error("invalid_video_id"))?;
+            Ok(id.to_string())
         }
+        // Validate the URL and extract video_id
         (None, Some(url)) => {
-            // Validate the URL and extract video_id
-            extract_id_from_url(url).map_err(|e| {
-                (
-                    StatusCode::BAD_REQUEST,
-                    e.message
-                        .map(|m| m.to_string())
-                        .unwrap_or_else(|| e.code.to_string()),
-                )
-            })?
+            extract_id_from_url(url).map_err(validation_error("invalid_video_url"))
+        }
+    }
+}
+
+// Mirrors the defaults YouTubeTranscriptApi uses when it builds its own client
+const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36";
+
+// Cheap to clone: the API and reqwest client both share their pools internally
+#[derive(Clone)]
+struct YtClient {
+    api: YouTubeTranscriptApi,
+    // Kept alongside the API so individual tracks can be fetched/translated directly
+    http: reqwest::Client,
+    has_proxy: bool,
+    has_cookies: bool,
+}
+
+// A proxy for plain-HTTP and HTTPS traffic; the library falls back to
+// whichever one is set when the other is missing
+#[derive(Clone)]
+struct ProxyEndpoint {
+    http: Option<String>,
+    https: Option<String>,
+}
+
+impl ProxyEndpoint {
+    fn both(url: &str) -> Self {
+        Self {
+            http: Some(url.to_string()),
+            https: Some(url.to_string()),
+        }
+    }
+
+    fn redacted(&self) -> String {
+        match (&self.http, &self.https) {
+            (Some(http), Some(https)) if http == https => redact_proxy_url(https),
+            (http, https) => format!(
+                "http={} https={}",
+                http.as_deref().map_or("-".into(), redact_proxy_url),
+                https.as_deref().map_or("-".into(), redact_proxy_url)
+            ),
         }
+    }
+}
+
+// Proxy URLs must be absolute http(s)/socks5 URLs with a host
+fn validate_proxy_url(url: &str) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| e.to_string())?;
+    if !matches!(parsed.scheme(), "http" | "https" | "socks5" | "socks5h") {
+        return Err(format!("unsupported scheme `{}`", parsed.scheme()));
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        return Err("missing host".to_string());
+    }
+    Ok(())
+}
+
+fn proxy_env(key: &str) -> Option<String> {
+    std::env::var(key)
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+// PROXY_URLS (comma-separated pool) takes precedence; otherwise PROXY_URL_HTTP
+// and PROXY_URL_HTTPS set each scheme, falling back to PROXY_URL for both
+fn configured_proxies() -> Vec<ProxyEndpoint> {
+    if let Some(pool) = proxy_env("PROXY_URLS") {
+        return pool
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(ProxyEndpoint::both)
+            .collect();
+    }
+
+    let fallback = proxy_env("PROXY_URL");
+    let endpoint = ProxyEndpoint {
+        http: proxy_env("PROXY_URL_HTTP").or_else(|| fallback.clone()),
+        https: proxy_env("PROXY_URL_HTTPS").or(fallback),
     };
+    if endpoint.http.is_none() && endpoint.https.is_none() {
+        return Vec::new();
+    }
+    vec![endpoint]
+}
 
-    // Create API instance with optional proxy
-    let proxy_url = std::env::var("PROXY_URL").ok();
-    let has_proxy = proxy_url.is_some();
-    println!("Proxy configured: {}", has_proxy);
-
-    let proxy_config = proxy_url.map(|url| {
-        let preview: String = url.chars().take(40).collect();
-        println!(
-            "Using proxy: {}{}",
-            preview,
-            if url.len() > 20 { "..." } else { "" }
+// Strips any `user:pass@` so proxy URLs are safe to log
+fn redact_proxy_url(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    let host = rest.split('/').next().unwrap_or(rest);
+    let host = host.rsplit_once('@').map_or(host, |(_, h)| h);
+    if scheme.is_empty() {
+        host.to_string()
+    } else {
+        format!("{}://{}", scheme, host)
+    }
+}
+
+const DEFAULT_PROXY_COOLDOWN_SECS: u64 = 60;
+
+#[derive(Default)]
+struct ProxyStats {
+    successes: u64,
+    failures: u64,
+    cooldown_until: Option<Instant>,
+}
+
+// Round-robin proxy pool that benches a proxy for a cooldown after it fails
+struct ProxyPool {
+    urls: Vec<ProxyEndpoint>,
+    // One client per proxy plus a direct one, built once at startup. Proxy
+    // config is only read from the environment then, so they never go stale
+    clients: Vec<YtClient>,
+    direct: YtClient,
+    cursor: AtomicUsize,
+    stats: Mutex<Vec<ProxyStats>>,
+    cooldown: Duration,
+}
+
+impl ProxyPool {
+    // Invalid entries are logged and dropped so a bad config disables that
+    // proxy instead of failing requests
+    fn from_env(cookies: Option<Arc<Jar>>) -> Result<Self, ApiError> {
+        let urls: Vec<ProxyEndpoint> = configured_proxies()
+            .into_iter()
+            .filter(|endpoint| {
+                let invalid = [&endpoint.http, &endpoint.https]
+                    .into_iter()
+                    .flatten()
+                    .find_map(|url| validate_proxy_url(url).err());
+                if let Some(reason) = &invalid {
+                    error!(proxy = %endpoint.redacted(), %reason, "ignoring invalid proxy URL");
+                }
+                invalid.is_none()
+            })
+            .collect();
+        let cooldown = std::env::var("PROXY_COOLDOWN_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_PROXY_COOLDOWN_SECS);
+        Self::new(urls, cookies, Duration::from_secs(cooldown))
+    }
+
+    fn new(
+        mut urls: Vec<ProxyEndpoint>,
+        cookies: Option<Arc<Jar>>,
+        cooldown: Duration,
+    ) -> Result<Self, ApiError> {
+        let mut clients = Vec::with_capacity(urls.len());
+        urls.retain(|endpoint| match build_api(Some(endpoint), cookies.clone()) {
+            Ok(client) => {
+                clients.push(client);
+                true
+            }
+            Err(e) => {
+                error!(proxy = %endpoint.redacted(), error = %e.message, "ignoring unusable proxy");
+                false
+            }
+        });
+        if !urls.is_empty() {
+            info!(proxies = urls.len(), "proxy pool configured");
+        }
+        Ok(Self {
+            stats: Mutex::new(urls.iter().map(|_| ProxyStats::default()).collect()),
+            urls,
+            clients,
+            direct: build_api(None, cookies)?,
+            cursor: AtomicUsize::new(0),
+            cooldown,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.urls.len()
+    }
+
+    // Next healthy proxy in rotation; if every proxy is cooling down, the one
+    // that recovers soonest
+    fn next(&self) -> Option<usize> {
+        if self.urls.is_empty() {
+            return None;
+        }
+
+        let now = Instant::now();
+        let stats = self.stats.lock().unwrap();
+        let start = self.cursor.fetch_add(1, Ordering::Relaxed);
+        (0..self.urls.len())
+            .map(|offset| (start + offset) % self.urls.len())
+            .find(|&i| stats[i].cooldown_until.is_none_or(|until| until <= now))
+            .or_else(|| {
+                (0..self.urls.len()).min_by_key(|&i| stats[i].cooldown_until.unwrap_or(now))
+            })
+    }
+
+    // The client for a proxy picked by `next`, or the direct one for `None`
+    fn client(&self, index: Option<usize>) -> YtClient {
+        index.map_or(&self.direct, |i| &self.clients[i]).clone()
+    }
+
+    fn record_success(&self, index: usize) {
+        let mut stats = self.stats.lock().unwrap();
+        stats[index].successes += 1;
+        stats[index].cooldown_until = None;
+    }
+
+    fn record_failure(&self, index: usize) {
+        let mut stats = self.stats.lock().unwrap();
+        let entry = &mut stats[index];
+        entry.failures += 1;
+        entry.cooldown_until = Some(Instant::now() + self.cooldown);
+        warn!(
+            proxy_index = index,
+            proxy = %self.urls[index].redacted(),
+            successes = entry.successes,
+            failures = entry.failures,
+            "proxy marked unhealthy"
         );
-        Box::new(GenericProxyConfig::new(Some(url.clone()), Some(url)).unwrap())
-            as Box<dyn yt_transcript_rs::proxies::ProxyConfig + Send + Sync>
-    });
+    }
+}
 
-    let api = YouTubeTranscriptApi::new(None, proxy_config, None).map_err(|e| {
-        (
+// YT_COOKIES_FILE: a Netscape-format cookies.txt from a signed-in browser, for
+// age-restricted and members-only videos. A file that can't be loaded is
+// logged and ignored, like an invalid proxy
+fn cookie_jar_from_env() -> Option<Arc<Jar>> {
+    let path = std::env::var("YT_COOKIES_FILE")
+        .ok()
+        .filter(|p| !p.trim().is_empty())?;
+    match CookieJarLoader::load_cookie_jar(std::path::Path::new(&path)) {
+        Ok(jar) => {
+            info!(path = %path, "loaded YouTube cookies");
+            Some(Arc::new(jar))
+        }
+        Err(e) => {
+            error!(path = %path, error = %e, "ignoring unusable YT_COOKIES_FILE");
+            None
+        }
+    }
+}
+
+fn build_api(
+    proxy: Option<&ProxyEndpoint>,
+    cookies: Option<Arc<Jar>>,
+) -> Result<YtClient, ApiError> {
+    // Create API instance with optional proxy
+    let has_proxy = proxy.is_some();
+    debug!(proxy_configured = has_proxy, "building YouTube client");
+
+    let proxy_config = proxy
+        .map(|endpoint| {
+            debug!(proxy = %endpoint.redacted(), "using proxy");
+            GenericProxyConfig::new(endpoint.http.clone(), endpoint.https.clone()).map_err(|e| {
+                ApiError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "client_init_failed",
+                    format!("Proxy config error: {}", e.0),
+                )
+            })
+        })
+        .transpose()?;
+
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert(
+        reqwest::header::ACCEPT_LANGUAGE,
+        reqwest::header::HeaderValue::from_static("en-US"),
+    );
+    let mut builder = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .default_headers(headers);
+
+    // The API ignores its own cookie path when handed a client, so the jar is
+    // attached here
+    let has_cookies = cookies.is_some();
+    if let Some(jar) = cookies {
+        builder = builder.cookie_provider(jar);
+    }
+
+    if let Some(proxy_config) = &proxy_config {
+        let proxy_map = proxy_config.to_requests_dict();
+        builder = builder.proxy(reqwest::Proxy::custom(move |url| {
+            proxy_map.get(url.scheme()).cloned()
+        }));
+    }
+
+    let http = builder.build().map_err(|e| {
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "client_init_failed",
+            format!("HTTP client init error: {}", e),
+        )
+    })?;
+
+    let api = YouTubeTranscriptApi::new(None, None, Some(http.clone())).map_err(|e| {
+        ApiError::new(
             StatusCode::INTERNAL_SERVER_ERROR,
+            "client_init_failed",
             format!("API init error: {}", e),
         )
     })?;
 
-    // Fetch transcript
-    let transcript = api
-        .fetch_transcript(&video_id, &["en"], false)
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Transcript error (proxy={}): {}", has_proxy, e),
-            )
-        })?;
+    Ok(YtClient {
+        api,
+        http,
+        has_proxy,
+        has_cookies,
+    })
+}
 
-    // Fetch video details
-    let details = api
-        .fetch_video_details(&video_id)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+const DEFAULT_LANGUAGES: &[&str] = &["en"];
 
-    let snippets: Vec<TranscriptSnippet> = transcript
-        .parts()
-        .iter()
-        .map(|snippet| TranscriptSnippet {
-            start: seconds_to_timestamp(snippet.start),
-            duration: snippet.duration,
-            text: snippet.text.replace(">> ", ""),
-        })
-        .collect();
+const LANGUAGE_NAMES: &[(&str, &str)] = &[
+    ("english", "en"),
+    ("spanish", "es"),
+    ("español", "es"),
+    ("french", "fr"),
+    ("français", "fr"),
+    ("german", "de"),
+    ("deutsch", "de"),
+    ("italian", "it"),
+    ("italiano", "it"),
+    ("portuguese", "pt"),
+    ("português", "pt"),
+    ("dutch", "nl"),
+    ("russian", "ru"),
+    ("polish", "pl"),
+    ("turkish", "tr"),
+    ("arabic", "ar"),
+    ("hindi", "hi"),
+    ("japanese", "ja"),
+    ("korean", "ko"),
+    ("chinese", "zh"),
+    ("indonesian", "id"),
+    ("vietnamese", "vi"),
+    ("ukrainian", "uk"),
+    ("swedish", "sv"),
+];
 
-    let formatted_views = format_views(&details.view_count);
+// Maps "English" to "en" and fixes casing and separators, so "EN", "en_us"
+// and "zh-hans" become "en", "en-US" and "zh-Hans"
+fn normalize_language(input: &str) -> String {
+    let input = input.trim();
+    let lowered = input.to_lowercase();
+    if let Some((_, code)) = LANGUAGE_NAMES.iter().find(|(name, _)| *name == lowered) {
+        return code.to_string();
+    }
 
-    Ok(Json(YTResponse {
-        id: video_id,
-        title: details.title,
-        author: details.author,
-        views: formatted_views,
-        transcript: snippets,
-    }))
+    lowered
+        .split(['-', '_'])
+        .enumerate()
+        .map(|(i, part)| match (i, part.len()) {
+            (0, _) => part.to_string(),
+            (_, 2) => part.to_ascii_uppercase(),
+            // Split on the first char, not byte 1: unvalidated input like
+            // "en-éab" would otherwise slice inside a multibyte character
+            (_, 4) => {
+                let mut chars = part.chars();
+                chars.next().map_or_else(String::new, |first| {
+                    first.to_ascii_uppercase().to_string() + chars.as_str()
+                })
+            }
+            _ => part.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("-")
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Error> {
-    dotenv().ok();
-    
-    // Add CORS layer
+// Expands requested codes against the codes a video actually has: exact
+// matches first, then tracks sharing the primary subtag (so "en" finds
+// "en-US" and "en-GB" finds "en")
+fn match_languages(requested: &[String], available: &[&str]) -> Vec<String> {
+    let primary = |code: &str| code.split('-').next().unwrap_or(code).to_ascii_lowercase();
+    let mut matched: Vec<String> = Vec::new();
+    for code in requested {
+        let exact = available.iter().filter(|a| a.eq_ignore_ascii_case(code));
+        let related = available
+            .iter()
+            .filter(|a| !a.eq_ignore_ascii_case(code) && primary(a) == primary(code));
+        for found in exact.chain(related) {
+            if !matched.iter().any(|m| m == found) {
+                matched.push(found.to_string());
+            }
+        }
+    }
+    matched
+}
+
+// ALLOWED_FORMATS (comma-separated, aliases like "md" accepted) restricts the
+// output formats; unset or nothing valid allows all of them
+fn allowed_formats_from_env() -> Vec<OutputFormat> {
+    let configured: Vec<OutputFormat> = std::env::var("ALLOWED_FORMATS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| {
+            let format = serde_json::from_value(serde_json::Value::String(name.clone())).ok();
+            if format.is_none() {
+                error!(format = %name, "ignoring invalid ALLOWED_FORMATS entry");
+            }
+            format
+        })
+        .collect();
+
+    if configured.is_empty() {
+        OutputFormat::ALL.to_vec()
+    } else {
+        configured
+    }
+}
+
+// DEFAULT_LANGUAGES (comma-separated priority list) overrides the built-in
+// default; invalid entries are logged and skipped
+fn default_languages_from_env() -> Vec<String> {
+    let configured: Vec<String> = std::env::var("DEFAULT_LANGUAGES")
+        .unwrap_or_default()
+        .split(',')
+        .map(normalize_language)
+        .filter(|code| !code.is_empty())
+        .filter(|code| match validate_language_code(code) {
+            Ok(()) => true,
+            Err(_) => {
+                error!(language = %code, "ignoring invalid DEFAULT_LANGUAGES entry");
+                false
+            }
+        })
+        .collect();
+
+    if configured.is_empty() {
+        DEFAULT_LANGUAGES.iter().map(|c| c.to_string()).collect()
+    } else {
+        configured
+    }
+}
+
+fn resolve_languages(
+    language: Option<&str>,
+    languages: Option<&[String]>,
+    defaults: &[String],
+) -> Result<Vec<String>, ApiError> {
+    let requested = match (language, languages) {
+        (Some(_), Some(_)) => {
+            return Err(ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "conflicting_language_input",
+                "Cannot provide both language and languages. Use one or the other.",
+            ));
+        }
+        (Some(code), None) => vec![normalize_language(code)],
+        (None, Some(codes)) if !codes.is_empty() => {
+            codes.iter().map(|c| normalize_language(c)).collect()
+        }
+        _ => defaults.to_vec(),
+    };
+
+    for code in &requested {
+        validate_language_code(code).map_err(validation_error("invalid_language"))?;
+    }
+
+    Ok(requested)
+}
+
+struct FetchOptions {
+    languages: Vec<String>,
+    translate_to: Option<String>,
+    prefer_manual: bool,
+    // Not part of the cache key: the transcript is the same either way
+    use_proxy: bool,
+    // Also left out of the key: `fetch_video_cached` re-checks it against the
+    // cached duration on every hit
+    max_duration: Option<u64>,
+    // Degraded responses aren't cached, so this stays out of the key too
+    require_details: bool,
+}
+
+fn transcript_error(e: CouldNotRetrieveTranscript, client: &YtClient) -> ApiError {
+    use CouldNotRetrieveTranscriptReason as Reason;
+    let has_proxy = client.has_proxy;
+
+    let (status, code) = match &e.reason {
+        Some(Reason::TranscriptsDisabled) => (StatusCode::NOT_FOUND, "transcripts_disabled"),
+        Some(Reason::NoTranscriptFound {
+            requested_language_codes,
+            ..
+        }) => {
+            return ApiError::new(
+                StatusCode::NOT_FOUND,
+                "no_transcript_found",
+                format!(
+                    "No transcript found for languages: {}",
+                    requested_language_codes.join(", ")
+                ),
+            );
+        }
+        Some(Reason::VideoUnavailable) => (StatusCode::NOT_FOUND, "video_unavailable"),
+        Some(Reason::InvalidVideoId) => (StatusCode::FORBIDDEN, "invalid_video_id"),
+        Some(Reason::VideoUnplayable { .. }) => (StatusCode::FORBIDDEN, "video_unplayable"),
+        Some(Reason::AgeRestricted) if !client.has_cookies => {
+            return ApiError::new(
+                StatusCode::FORBIDDEN,
+                "authentication_required",
+                "This video requires a signed-in account, and no cookies are configured. \
+                 Set YT_COOKIES_FILE to fetch it.",
+            );
+        }
+        Some(Reason::AgeRestricted) => (StatusCode::FORBIDDEN, "age_restricted"),
+        Some(Reason::TranslationUnavailable(_) | Reason::TranslationLanguageUnavailable(_)) => {
+            (StatusCode::BAD_REQUEST, "translation_unavailable")
+        }
+        _ => {
+            return ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "transcript_fetch_failed",
+                format!("Transcript error (proxy={}): {}", has_proxy, e),
+            );
+        }
+    };
+
+    ApiError::new(status, code, e.to_string())
+}
+
+const DEFAULT_CACHE_TTL_SECS: u64 = 300;
+const CACHE_MAX_ENTRIES: usize = 256;
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    video_id: String,
+    languages: Vec<String>,
+    translate_to: Option<String>,
+    prefer_manual: bool,
+}
+
+struct CacheEntry {
+    response: YTResponse,
+    inserted: Instant,
+    last_used: Instant,
+}
+
+// Small in-memory LRU of fetched transcripts + details, expiring after `ttl`
+struct TranscriptCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<HashMap<CacheKey, CacheEntry>>,
+}
+
+impl TranscriptCache {
+    fn from_env() -> Self {
+        let ttl = std::env::var("CACHE_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_CACHE_TTL_SECS);
+        Self::new(Duration::from_secs(ttl), CACHE_MAX_ENTRIES)
+    }
+
+    fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<YTResponse> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        match entries.get_mut(key) {
+            Some(entry) if now.duration_since(entry.inserted) < self.ttl => {
+                entry.last_used = now;
+                Some(entry.response.clone())
+            }
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, key: CacheKey, response: YTResponse) {
+        // A zero TTL disables caching entirely
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, entry| now.duration_since(entry.inserted) < self.ttl);
+        if entries.len() >= self.capacity
+            && let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+        {
+            entries.remove(&oldest);
+        }
+        entries.insert(
+            key,
+            CacheEntry {
+                response,
+                inserted: now,
+                last_used: now,
+            },
+        );
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+// Per-client token bucket: `capacity` requests per `window`, refilled continuously
+struct RateLimiter {
+    capacity: f64,
+    window: Duration,
+    buckets: Mutex<HashMap<String, Bucket>>,
+    last_cleanup: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    // Enabled only when RATE_LIMIT_REQUESTS is set to a positive number
+    fn from_env() -> Option<Self> {
+        let capacity: u32 = std::env::var("RATE_LIMIT_REQUESTS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&n| n > 0)?;
+        let window_secs: u64 = std::env::var("RATE_LIMIT_WINDOW_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&n| n > 0)
+            .unwrap_or(60);
+        Some(Self {
+            capacity: capacity as f64,
+            window: Duration::from_secs(window_secs),
+            buckets: Mutex::new(HashMap::new()),
+            last_cleanup: Mutex::new(Instant::now()),
+        })
+    }
+
+    // Returns Err(retry_after_secs) when the client is out of tokens
+    fn check(&self, client: &str) -> Result<(), u64> {
+        let now = Instant::now();
+        let rate = self.capacity / self.window.as_secs_f64();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        // Periodically drop buckets that have fully refilled
+        let mut last_cleanup = self.last_cleanup.lock().unwrap();
+        if now.duration_since(*last_cleanup) >= self.window {
+            buckets.retain(|_, b| now.duration_since(b.updated) < self.window);
+            *last_cleanup = now;
+        }
+
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(self.capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / rate).ceil() as u64)
+        }
+    }
+}
+
+struct AppState {
+    cache: TranscriptCache,
+    proxies: ProxyPool,
+    rate_limiter: Option<RateLimiter>,
+    // From the comma-separated API_KEYS env var; empty leaves the API open
+    api_keys: Vec<String>,
+    profanity: Vec<String>,
+    default_languages: Vec<String>,
+    allowed_formats: Vec<OutputFormat>,
+    // MAX_VIDEO_DURATION_SECS; unset means no limit
+    max_video_duration: Option<u64>,
+    metrics: PrometheusHandle,
+    in_flight: InFlight,
+    // Flipped once startup warm-up finishes; see `ready`
+    ready: AtomicBool,
+}
+
+impl AppState {
+    // The format an Accept header asked for, unless ALLOWED_FORMATS disables
+    // it; the request then falls back to the default instead of failing
+    fn negotiated_format(&self, accepted: Option<OutputFormat>) -> Option<OutputFormat> {
+        accepted.filter(|format| self.allowed_formats.contains(format))
+    }
+
+    // The duration cap for a request, lifted when the client opts in
+    fn max_duration(&self, allow_long: bool) -> Option<u64> {
+        self.max_video_duration.filter(|_| !allow_long)
+    }
+}
+
+const DEFAULT_FETCH_TIMEOUT_MS: u64 = 15_000;
+
+fn fetch_timeout() -> Duration {
+    let ms = std::env::var("FETCH_TIMEOUT_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_FETCH_TIMEOUT_MS);
+    Duration::from_millis(ms)
+}
+
+// Runs `fut` against a deadline shared by all fetches for one request
+async fn with_deadline<T>(
+    deadline: Instant,
+    what: &str,
+    fut: impl Future<Output = Result<T, ApiError>>,
+) -> Result<T, ApiError> {
+    tokio::time::timeout_at(deadline, fut).await.map_err(|_| {
+        ApiError::new(
+            StatusCode::GATEWAY_TIMEOUT,
+            "fetch_timeout",
+            format!("Timed out fetching {} from YouTube.", what),
+        )
+    })?
+}
+
+const DEFAULT_RETRY_ATTEMPTS: u32 = 3;
+const DEFAULT_RETRY_BASE_MS: u64 = 250;
+
+fn is_transient(e: &CouldNotRetrieveTranscript) -> bool {
+    matches!(
+        e.reason,
+        Some(
+            CouldNotRetrieveTranscriptReason::YouTubeRequestFailed(_)
+                | CouldNotRetrieveTranscriptReason::RequestBlocked(_)
+        )
+    )
+}
+
+// Retries transient failures (network errors, rate limiting) with exponential backoff + jitter
+async fn with_retry<T, F, Fut>(what: &str, mut op: F) -> Result<T, CouldNotRetrieveTranscript>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, CouldNotRetrieveTranscript>>,
+{
+    let attempts = std::env::var("FETCH_RETRY_ATTEMPTS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_RETRY_ATTEMPTS)
+        .max(1);
+    let base_ms = std::env::var("FETCH_RETRY_BASE_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_RETRY_BASE_MS);
+
+    let mut attempt = 1;
+    loop {
+        match op().await {
+            Err(e) if attempt < attempts && is_transient(&e) => {
+                let delay = base_ms.saturating_mul(2u64.saturating_pow(attempt - 1));
+                let nanos = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.subsec_nanos() as u64)
+                    .unwrap_or_default();
+                let jitter = nanos % (delay / 2 + 1);
+                warn!(
+                    attempt = attempt + 1,
+                    attempts,
+                    delay_ms = delay + jitter,
+                    error = %e,
+                    "retrying {}",
+                    what
+                );
+                tokio::time::sleep(Duration::from_millis(delay + jitter)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn fetch_track(
+    client: &YtClient,
+    video_id: &str,
+    options: &FetchOptions,
+) -> Result<FetchedTranscript, ApiError> {
+    // Pick the best track, translating it first if requested. `find_transcript`
+    // goes language by language; preferring manual tracks instead takes a
+    // human-authored track in any requested language over an auto-generated one
+    let list = with_retry("transcript list", || client.api.list_transcripts(video_id))
+        .await
+        .map_err(|e| transcript_error(e, client))?;
+    let mut available: Vec<&str> = list.transcripts().map(|t| t.language_code()).collect();
+    available.sort_unstable();
+    available.dedup();
+    let matched = match_languages(&options.languages, &available);
+    if matched.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "no_transcript_found",
+            format!(
+                "No transcript found for languages: {}. Available: {}",
+                options.languages.join(", "),
+                if available.is_empty() {
+                    "none".to_string()
+                } else {
+                    available.join(", ")
+                }
+            ),
+        ));
+    }
+    let language_refs: Vec<&str> = matched.iter().map(String::as_str).collect();
+    let manual = options
+        .prefer_manual
+        .then(|| list.find_manually_created_transcript(&language_refs).ok())
+        .flatten();
+    let track = match manual {
+        Some(track) => track,
+        // No human track in any requested language: fall back to the
+        // auto-generated one in the same request; `generated` reports it
+        None if options.prefer_manual => {
+            debug!(video_id, "no manual track, falling back to auto-generated");
+            list.find_generated_transcript(&language_refs)
+                .map_err(|e| transcript_error(e, client))?
+        }
+        None => list
+            .find_transcript(&language_refs)
+            .map_err(|e| transcript_error(e, client))?,
+    };
+
+    let track = match &options.translate_to {
+        Some(target) => {
+            if !track.is_translatable() {
+                return Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "translation_unavailable",
+                    format!(
+                        "The {} transcript is not translatable.",
+                        track.language_code()
+                    ),
+                ));
+            }
+            track
+                .translate(target)
+                .map_err(|e| transcript_error(e, client))?
+        }
+        None => track,
+    };
+
+    with_retry("transcript", || track.fetch(&client.http, false))
+        .await
+        .map_err(|e| transcript_error(e, client))
+}
+
+impl ApiError {
+    // Failures that may be the proxy's fault rather than the video's
+    fn is_upstream_failure(&self) -> bool {
+        matches!(
+            self.code,
+            "transcript_fetch_failed" | "playlist_fetch_failed" | "fetch_timeout"
+        )
+    }
+}
+
+// Runs `fetch` through the proxy pool, failing over to the next healthy proxy
+// when one produces an upstream error
+async fn with_failover<T, F, Fut>(state: &AppState, mut fetch: F) -> Result<T, ApiError>
+where
+    F: FnMut(YtClient) -> Fut,
+    Fut: Future<Output = Result<T, ApiError>>,
+{
+    let attempts = state.proxies.len().max(1);
+    for attempt in 1..=attempts {
+        let proxy = state.proxies.next();
+        let result = fetch(state.proxies.client(proxy)).await;
+
+        let Some(index) = proxy else {
+            return result;
+        };
+        // The last attempt's proxy is the one that served (or failed) the request
+        Span::current().record("proxy_index", index);
+        match result {
+            Err(e) if e.is_upstream_failure() => {
+                state.proxies.record_failure(index);
+                if attempt == attempts {
+                    return Err(e);
+                }
+                warn!(proxy_index = index, error = %e.message, "failing over to next proxy");
+            }
+            result => {
+                state.proxies.record_success(index);
+                return result;
+            }
+        }
+    }
+    unreachable!("the final attempt always returns")
+}
+
+async fn fetch_video(
+    state: &AppState,
+    video_id: String,
+    options: &FetchOptions,
+) -> Result<YTResponse, ApiError> {
+    if !options.use_proxy {
+        return fetch_video_with(&state.proxies.client(None), video_id, options).await;
+    }
+    with_failover(state, |client| {
+        let video_id = video_id.clone();
+        async move { fetch_video_with(&client, video_id, options).await }
+    })
+    .await
+}
+
+// YouTube serves this for every video, even when details can't be fetched
+fn default_thumbnail_url(video_id: &str) -> String {
+    format!("https://i.ytimg.com/vi/{}/hqdefault.jpg", video_id)
+}
+
+impl VideoDetailsResponse {
+    // Stands in for details that couldn't be fetched when the client said
+    // they aren't required
+    fn unavailable(video_id: &str) -> Self {
+        Self {
+            id: video_id.to_string(),
+            title: String::new(),
+            author: String::new(),
+            views: String::new(),
+            view_count: None,
+            published_at: None,
+            duration_seconds: None,
+            duration: None,
+            thumbnail_url: default_thumbnail_url(video_id),
+            thumbnails: Vec::new(),
+            description: String::new(),
+            tags: Vec::new(),
+        }
+    }
+}
+
+// The display string plus the raw count; a count YouTube didn't give as a
+// plain number passes through as-is, with no raw value
+fn parse_views(raw: &str) -> (String, Option<u64>) {
+    (format_views(raw, &EN_LOCALE), raw.parse().ok())
+}
+
+// Details plus the best-effort publish date, shared by the transcript and
+// metadata-only endpoints
+async fn fetch_details(
+    client: &YtClient,
+    video_id: &str,
+    deadline: Instant,
+) -> Result<VideoDetailsResponse, ApiError> {
+    let api = &client.api;
+
+    let details = with_deadline(deadline, "video details", async {
+        with_retry("video details", || api.fetch_video_details(video_id))
+            .await
+            .map_err(|e| transcript_error(e, client))
+    })
+    .await?;
+
+    // Publish date lives in the microformat data; it's optional so a failure here is ignored
+    let published_at = tokio::time::timeout_at(deadline, api.fetch_microformat(video_id))
+        .await
+        .ok()
+        .and_then(Result::ok)
+        .and_then(|m| m.publish_date);
+
+    let (views, view_count) = parse_views(&details.view_count);
+
+    // A zero length means the details didn't carry one (e.g. upcoming streams)
+    let duration_seconds = Some(details.length_seconds as u64).filter(|&secs| secs > 0);
+
+    let thumbnails: Vec<Thumbnail> = details
+        .thumbnails
+        .iter()
+        .map(|t| Thumbnail {
+            url: t.url.clone(),
+            width: t.width,
+            height: t.height,
+        })
+        .collect();
+    let thumbnail_url = thumbnails
+        .iter()
+        .max_by_key(|t| t.width)
+        .map(|t| t.url.clone())
+        .unwrap_or_else(|| default_thumbnail_url(video_id));
+
+    Ok(VideoDetailsResponse {
+        id: video_id.to_string(),
+        title: details.title,
+        author: details.author,
+        views,
+        view_count,
+        published_at,
+        duration_seconds,
+        duration: duration_seconds.map(|secs| seconds_to_timestamp(secs as f64)),
+        thumbnail_url,
+        thumbnails,
+        description: details.short_description,
+        tags: details.keywords.unwrap_or_default(),
+    })
+}
+
+async fn fetch_video_with(
+    client: &YtClient,
+    video_id: String,
+    options: &FetchOptions,
+) -> Result<YTResponse, ApiError> {
+    let deadline = Instant::now() + fetch_timeout();
+
+    // The two round trips are independent and the client is safe to share,
+    // so they run concurrently. An over-long video fails the details side as
+    // soon as it's known, which drops the transcript download mid-flight
+    let details = async {
+        let details = fetch_details(client, &video_id, deadline).await;
+        if let Ok(details) = &details {
+            check_duration(options.max_duration, details.duration_seconds)?;
+        }
+        Ok::<_, ApiError>(details)
+    };
+    let transcript = async {
+        Ok::<_, ApiError>(
+            with_deadline(
+                deadline,
+                "transcript",
+                fetch_track(client, &video_id, options),
+            )
+            .await,
+        )
+    };
+    let (details, transcript) = tokio::try_join!(details, transcript)?;
+
+    // A transcript failure (e.g. no track in the requested language) is the
+    // more useful error, so it wins when both fail
+    let transcript = transcript?;
+    let (details, details_unavailable) = match details {
+        Ok(details) => (details, false),
+        Err(e) if !options.require_details => {
+            warn!(video_id = %video_id, error = %e.message, "video details unavailable, returning captions only");
+            (VideoDetailsResponse::unavailable(&video_id), true)
+        }
+        Err(e) => return Err(e),
+    };
+
+    let snippets: Vec<TranscriptSnippet> = transcript
+        .parts()
+        .iter()
+        .enumerate()
+        .map(|(position, snippet)| to_snippet(position, snippet))
+        .collect();
+
+    Ok(YTResponse {
+        input: None,
+        canonical_url: None,
+        id: details.id,
+        title: details.title,
+        author: details.author,
+        views: details.views,
+        view_count: details.view_count,
+        published_at: details.published_at,
+        duration_seconds: details.duration_seconds,
+        duration: details.duration,
+        start_seconds: None,
+        thumbnail_url: details.thumbnail_url,
+        thumbnails: details.thumbnails,
+        language: transcript.language_code().to_string(),
+        is_translated: options.translate_to.is_some(),
+        generated: transcript.is_generated(),
+        quality_hint: QualityHint::new(transcript.is_generated(), options.translate_to.is_some()),
+        full_text: None,
+        // Always parsed so cached entries can serve `include_chapters` requests
+        chapters: Some(parse_chapters(&details.description)),
+        stats: None,
+        detected_language: None,
+        detected_language_confidence: None,
+        sounds: None,
+        transformations: Vec::new(),
+        details_unavailable,
+        total: None,
+        has_more: None,
+        truncated: None,
+        total_matches: None,
+        transcript: snippets,
+    })
+}
+
+fn check_duration(max_duration: Option<u64>, duration: Option<u64>) -> Result<(), ApiError> {
+    if let (Some(max), Some(secs)) = (max_duration, duration)
+        && secs > max
+    {
+        return Err(ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "video_too_long",
+            format!(
+                "Video is {} long, over the {} limit. Set allow_long to fetch it anyway.",
+                seconds_to_timestamp(secs as f64),
+                seconds_to_timestamp(max as f64)
+            ),
+        ));
+    }
+    Ok(())
+}
+
+async fn fetch_video_cached(
+    state: &AppState,
+    video_id: String,
+    options: &FetchOptions,
+) -> Result<(YTResponse, bool), ApiError> {
+    let key = CacheKey {
+        video_id: video_id.clone(),
+        languages: options.languages.clone(),
+        translate_to: options.translate_to.clone(),
+        prefer_manual: options.prefer_manual,
+    };
+    if let Some(response) = state.cache.get(&key) {
+        counter!("transcript_cache_total", "result" => "hit").increment(1);
+        // The entry may have been fetched by an `allow_long` request, so the
+        // limit is this caller's to enforce
+        check_duration(options.max_duration, response.duration_seconds)?;
+        return Ok((response, true));
+    }
+    counter!("transcript_cache_total", "result" => "miss").increment(1);
+
+    let response = fetch_video(state, video_id, options).await?;
+    if !response.details_unavailable {
+        state.cache.insert(key, response.clone());
+    }
+    Ok((response, false))
+}
+
+fn canonical_url(video_id: &str) -> String {
+    format!("https://youtu.be/{}", video_id)
+}
+
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+fn to_json<T: Serialize>(value: &T, pretty: bool) -> String {
+    if pretty {
+        serde_json::to_string_pretty(value).unwrap_or_default()
+    } else {
+        serde_json::to_string(value).unwrap_or_default()
+    }
+}
+
+// Serializes the response in the requested format as (content type, body)
+fn render_transcript(payload: &YTRequest, mut response: YTResponse) -> (&'static str, String) {
+    let snippets = &response.transcript;
+
+    match payload.format.unwrap_or_default() {
+        OutputFormat::Srt => {
+            return ("text/plain; charset=utf-8", to_srt(snippets));
+        }
+        OutputFormat::Vtt => {
+            return ("text/vtt; charset=utf-8", to_vtt(snippets));
+        }
+        OutputFormat::Text => {
+            return (
+                "text/plain; charset=utf-8",
+                to_plain_text(snippets, payload.text_separator()),
+            );
+        }
+        OutputFormat::Csv => {
+            return ("text/csv; charset=utf-8", to_csv(snippets));
+        }
+        OutputFormat::Markdown => {
+            return (
+                "text/markdown; charset=utf-8",
+                to_markdown(&response.id, snippets),
+            );
+        }
+        OutputFormat::Jsonl => {
+            return ("application/x-ndjson", to_jsonl(snippets));
+        }
+        OutputFormat::Html => {
+            return ("text/html; charset=utf-8", to_html(&response.id, snippets));
+        }
+        OutputFormat::Json => {}
+    }
+
+    if let Some(chunk) = &payload.chunk {
+        let chunked = ChunkedResponse {
+            chunks: to_chunks(&response.transcript, chunk.max_chars, chunk.overlap),
+            id: response.id,
+            title: response.title,
+            author: response.author,
+            views: response.views,
+            transformations: response.transformations,
+        };
+        return (JSON_CONTENT_TYPE, to_json(&chunked, payload.pretty));
+    }
+
+    if payload.group_speakers {
+        let grouped = SpeakerResponse {
+            turns: to_speaker_turns(&response.transcript),
+            id: response.id,
+            title: response.title,
+            author: response.author,
+            views: response.views,
+            transformations: response.transformations,
+        };
+        return (JSON_CONTENT_TYPE, to_json(&grouped, payload.pretty));
+    }
+
+    if !payload.include_chapters {
+        response.chapters = None;
+    } else if let Some(chapters) = &response.chapters {
+        assign_chapters(&mut response.transcript, chapters);
+    }
+
+    if payload.include_stats {
+        response.stats = Some(transcript_stats(&response.transcript));
+    }
+
+    // No guess at all when the text is too short or ambiguous to call
+    if payload.detect_language
+        && let Some(info) = whatlang::detect(&to_plain_text(&response.transcript, " "))
+    {
+        response.detected_language = Some(info.lang().code().to_string());
+        response.detected_language_confidence = Some((info.confidence() * 100.0).round() / 100.0);
+    }
+
+    if payload.include_full_text {
+        response.full_text = Some(to_plain_text(
+            &response.transcript,
+            payload.join_separator.as_deref().unwrap_or(" "),
+        ));
+    }
+
+    response.input = payload
+        .video_url
+        .clone()
+        .or_else(|| payload.video_id.clone());
+    response.canonical_url = Some(canonical_url(&response.id));
+    response.start_seconds = payload
+        .video_url
+        .as_deref()
+        .and_then(extract_start_from_url);
+
+    (JSON_CONTENT_TYPE, to_json(&response, payload.pretty))
+}
+
+fn etag_for(body: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+// Builds the final response with ETag/X-Cache headers, answering 304 when the
+// client's If-None-Match already covers this body (only passed for GET requests)
+fn finish_transcript_response(
+    (content_type, body): (&'static str, String),
+    cache_hit: bool,
+    if_none_match: Option<&str>,
+) -> Response {
+    let etag = etag_for(&body);
+    let mut res = if if_none_match.is_some_and(|inm| etag_matches(inm, &etag)) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        ([(header::CONTENT_TYPE, content_type)], body).into_response()
+    };
+
+    let headers = res.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        headers.insert(header::ETAG, value);
+    }
+    headers.insert(
+        "x-cache",
+        HeaderValue::from_static(if cache_hit { "HIT" } else { "MISS" }),
+    );
+    // The body can depend on Accept and Accept-Language, so shared caches
+    // must key on them
+    headers.append(
+        header::VARY,
+        HeaderValue::from_static("accept, accept-language"),
+    );
+    res
+}
+
+async fn transcript(
+    state: &AppState,
+    payload: &YTRequest,
+) -> Result<((&'static str, String), bool), ApiError> {
+    let format = payload.format.unwrap_or_default();
+    if !state.allowed_formats.contains(&format) {
+        let allowed: Vec<&str> = state.allowed_formats.iter().map(|f| f.name()).collect();
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "format_not_allowed",
+            format!(
+                "The {} format is disabled. Allowed formats: {}",
+                format.name(),
+                allowed.join(", ")
+            ),
+        ));
+    }
+    if payload.multi {
+        return multi_transcript(state, payload).await;
+    }
+    let (response, cache_hit) = prepare_transcript(state, payload).await?;
+    Ok((render_transcript(payload, response), cache_hit))
+}
+
+// Validates the request, fetches through the cache and applies every
+// snippet-level option, leaving only the output format to the caller
+async fn prepare_transcript(
+    state: &AppState,
+    payload: &YTRequest,
+) -> Result<(YTResponse, bool), ApiError> {
+    let video_id = resolve_video_id(payload.video_id.as_deref(), payload.video_url.as_deref())?;
+
+    // Falling back to the defaults keeps an unavailable browser language from
+    // turning into a 404
+    let mut defaults = payload.accept_languages.clone();
+    for code in &state.default_languages {
+        if !defaults.contains(code) {
+            defaults.push(code.clone());
+        }
+    }
+    let languages = resolve_languages(
+        payload.language.as_deref(),
+        payload.languages.as_deref(),
+        &defaults,
+    )?;
+    let translate_to = payload.translate_to.as_deref().map(normalize_language);
+    if let Some(target) = &translate_to {
+        validate_language_code(target).map_err(validation_error("invalid_language"))?;
+    }
+    let options = FetchOptions {
+        languages,
+        translate_to,
+        prefer_manual: payload.prefer_manual.unwrap_or(true),
+        use_proxy: payload.use_proxy.unwrap_or(true),
+        max_duration: state.max_duration(payload.allow_long),
+        require_details: payload.require_details.unwrap_or(true),
+    };
+    validate_transcript_options(payload)?;
+
+    let span = Span::current();
+    span.record("video_id", video_id.as_str());
+    span.record("language", options.languages.join(",").as_str());
+
+    let (mut response, cache_hit) = fetch_video_cached(state, video_id, &options).await?;
+    span.record("cache", if cache_hit { "HIT" } else { "MISS" });
+    apply_transcript_options(state, payload, &mut response);
+
+    Ok((response, cache_hit))
+}
+
+// Fetches each requested language concurrently; languages that fail are
+// reported in `errors` as long as at least one succeeds
+async fn multi_transcript(
+    state: &AppState,
+    payload: &YTRequest,
+) -> Result<((&'static str, String), bool), ApiError> {
+    let video_id = resolve_video_id(payload.video_id.as_deref(), payload.video_url.as_deref())?;
+    let languages = resolve_languages(
+        payload.language.as_deref(),
+        payload.languages.as_deref(),
+        &state.default_languages,
+    )?;
+    if payload.translate_to.is_some() {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "conflicting_multi_options",
+            "translate_to cannot be combined with multi.",
+        ));
+    }
+    if payload.format.is_some_and(|f| f != OutputFormat::Json) {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "conflicting_multi_options",
+            "multi responses are only available as JSON.",
+        ));
+    }
+    validate_transcript_options(payload)?;
+    Span::current().record("video_id", video_id.as_str());
+    Span::current().record("language", languages.join(",").as_str());
+
+    let prefer_manual = payload.prefer_manual.unwrap_or(true);
+    let results = future::join_all(languages.into_iter().map(|code| {
+        let video_id = video_id.clone();
+        async move {
+            let options = FetchOptions {
+                languages: vec![code.clone()],
+                translate_to: None,
+                prefer_manual,
+                use_proxy: payload.use_proxy.unwrap_or(true),
+                max_duration: state.max_duration(payload.allow_long),
+                require_details: payload.require_details.unwrap_or(true),
+            };
+            (code, fetch_video_cached(state, video_id, &options).await)
+        }
+    }))
+    .await;
+
+    let mut meta = None;
+    let mut first_error = None;
+    let mut all_hits = true;
+    let mut transcripts = BTreeMap::new();
+    let mut transformations = BTreeMap::new();
+    let mut errors = BTreeMap::new();
+    for (code, result) in results {
+        match result {
+            Ok((mut response, cache_hit)) => {
+                all_hits &= cache_hit;
+                apply_transcript_options(state, payload, &mut response);
+                transformations.insert(code.clone(), std::mem::take(&mut response.transformations));
+                transcripts.insert(code, std::mem::take(&mut response.transcript));
+                meta.get_or_insert(response);
+            }
+            Err(e) => {
+                errors.insert(
+                    code,
+                    LanguageError {
+                        error: e.message.clone(),
+                        code: e.code,
+                    },
+                );
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    let Some(meta) = meta else {
+        return Err(first_error.expect("at least one language is always requested"));
+    };
+    let body = to_json(
+        &MultiLanguageResponse {
+            id: meta.id,
+            title: meta.title,
+            author: meta.author,
+            views: meta.views,
+            transcripts,
+            transformations,
+            errors,
+        },
+        payload.pretty,
+    );
+    Ok(((JSON_CONTENT_TYPE, body), all_hits))
+}
+
+fn validate_transcript_options(payload: &YTRequest) -> Result<(), ApiError> {
+    if let (Some(after), Some(before)) = (payload.start_after, payload.end_before)
+        && after >= before
+    {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_time_range",
+            "start_after must be less than end_before.",
+        ));
+    }
+
+    if let Some(chunk) = &payload.chunk
+        && (chunk.max_chars == 0 || chunk.overlap >= chunk.max_chars)
+    {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_chunk_options",
+            "chunk.max_chars must be positive and greater than chunk.overlap.",
+        ));
+    }
+
+    if payload
+        .search
+        .as_deref()
+        .is_some_and(|q| q.trim().is_empty())
+    {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_search",
+            "search must not be empty.",
+        ));
+    }
+
+    if payload.preview_seconds.is_some_and(|secs| secs <= 0.0) {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_preview",
+            "preview_seconds must be positive.",
+        ));
+    }
+
+    if payload
+        .join_separator
+        .as_ref()
+        .is_some_and(|s| s.chars().count() > MAX_JOIN_SEPARATOR_CHARS)
+    {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_join_separator",
+            format!(
+                "join_separator must be at most {} characters.",
+                MAX_JOIN_SEPARATOR_CHARS
+            ),
+        ));
+    }
+
+    if let Some(context) = payload.context_seconds {
+        if payload.search.is_none() {
+            return Err(ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_search",
+                "context_seconds requires search.",
+            ));
+        }
+        if !context.is_finite() || context < 0.0 {
+            return Err(ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_search",
+                "context_seconds must be zero or positive.",
+            ));
+        }
+    }
+
+    if payload.max_matches == Some(0) {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_search",
+            "max_matches must be positive.",
+        ));
+    }
+
+    if payload.limit == Some(0) {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_pagination",
+            "limit must be positive.",
+        ));
+    }
+
+    // Stripping first would leave nothing to extract
+    if payload.extract_sounds && payload.strip_brackets {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "conflicting_sound_options",
+            "extract_sounds cannot be combined with strip_brackets.",
+        ));
+    }
+
+    Ok(())
+}
+
+// Applies `rewrite` to every snippet's text, reporting whether any changed
+fn rewrite_text(snippets: &mut [TranscriptSnippet], rewrite: impl Fn(&str) -> String) -> bool {
+    let mut changed = false;
+    for snippet in snippets {
+        let text = rewrite(&snippet.text);
+        if text != snippet.text {
+            snippet.text = text;
+            changed = true;
+        }
+    }
+    changed
+}
+
+impl YTRequest {
+    // For the text format; `full_text` ignores line_breaks
+    fn text_separator(&self) -> &str {
+        match &self.join_separator {
+            Some(separator) => separator,
+            None if self.line_breaks => "\n",
+            None => " ",
+        }
+    }
+}
+
+const MAX_JOIN_SEPARATOR_CHARS: usize = 16;
+
+// Enough for any sensible search; common words in long videos can match thousands
+const DEFAULT_MAX_MATCHES: usize = 500;
+
+// Every snippet-level option, in order; the request is already validated.
+// Text transforms that actually changed something are listed in
+// `transformations`, so clients can tell when text isn't verbatim
+fn apply_transcript_options(state: &AppState, payload: &YTRequest, response: &mut YTResponse) {
+    let mut applied = Vec::new();
+    // `>>` markers are stripped at fetch time, before the transcript is cached
+    if response.transcript.iter().any(|s| s.speaker_change) {
+        applied.push("strip_prefix");
+    }
+    // A hard cap from the start of the video, applied before any other filtering
+    if let Some(preview) = payload.preview_seconds {
+        let total = response.transcript.len();
+        response
+            .transcript
+            .retain(|snippet| snippet.start_seconds <= preview);
+        response.truncated = Some(response.transcript.len() < total);
+    }
+    if payload.clean.unwrap_or(true) && rewrite_text(&mut response.transcript, clean_text) {
+        applied.push("clean");
+    }
+    if payload.strip_brackets {
+        // A caption that was only an annotation has nothing left to show
+        let mut changed = false;
+        response.transcript.retain_mut(|snippet| {
+            let text = strip_bracketed(&snippet.text);
+            changed |= text != snippet.text;
+            snippet.text = text;
+            !snippet.text.is_empty()
+        });
+        if changed {
+            applied.push("strip_brackets");
+        }
+    }
+    if payload.dedup {
+        let before = response.transcript.len();
+        dedup_rolling(&mut response.transcript);
+        if response.transcript.len() < before {
+            applied.push("dedup");
+        }
+    }
+    filter_time_range(
+        &mut response.transcript,
+        payload.start_after,
+        payload.end_before,
+    );
+    // After the time filter so only cues inside the requested range are kept
+    if payload.extract_sounds {
+        let mut sounds = Vec::new();
+        response.transcript.retain_mut(|snippet| {
+            let (text, cues) = extract_bracketed(&snippet.text);
+            sounds.extend(cues.into_iter().map(|cue| SoundCue {
+                start: snippet.start.clone(),
+                start_seconds: snippet.start_seconds,
+                text: cue,
+            }));
+            snippet.text = text;
+            !snippet.text.is_empty()
+        });
+        if !sounds.is_empty() {
+            applied.push("extract_sounds");
+        }
+        response.sounds = Some(sounds);
+    }
+    if payload.sentences {
+        let sentences = to_sentences(&response.transcript);
+        // Captions that already end on sentence boundaries come back as-is
+        let changed = sentences.len() != response.transcript.len()
+            || sentences
+                .iter()
+                .zip(&response.transcript)
+                .any(|(sentence, snippet)| sentence.text != snippet.text);
+        if changed {
+            applied.push("sentences");
+        }
+        response.transcript = sentences;
+    }
+    if let Some(query) = payload.search.as_deref().map(|q| q.trim().to_lowercase()) {
+        // No matches is still a valid (empty) transcript, not an error
+        let mut matches: Vec<usize> = (0..response.transcript.len())
+            .filter(|&i| matches_query(&response.transcript[i].text, &query, payload.whole_word))
+            .collect();
+        let total = matches.len();
+        let max = payload.max_matches.unwrap_or(DEFAULT_MAX_MATCHES);
+        if total > max {
+            warn!(
+                total_matches = total,
+                max_matches = max,
+                "truncating search results"
+            );
+            matches.truncate(max);
+        }
+        let mut keep = vec![false; response.transcript.len()];
+        matches.iter().for_each(|&i| keep[i] = true);
+        // Each match grows into a window of surrounding snippets. Overlap is
+        // strict: a snippet that only touches a window's edge stays out
+        if let Some(context) = payload.context_seconds {
+            let spans: Vec<(f64, f64)> = matches
+                .iter()
+                .map(|&i| &response.transcript[i])
+                .map(|s| (s.start_seconds, s.start_seconds + s.duration))
+                .collect();
+            let windows = merge_windows(&spans, context);
+            for (keep, snippet) in keep.iter_mut().zip(&response.transcript) {
+                let (start, end) = (
+                    snippet.start_seconds,
+                    snippet.start_seconds + snippet.duration,
+                );
+                // First window that ends after this snippet starts
+                let i = windows.partition_point(|&(_, window_end)| window_end <= start);
+                *keep |= windows
+                    .get(i)
+                    .is_some_and(|&(window_start, _)| window_start < end);
+            }
+        }
+        let mut keep = keep.into_iter();
+        response.transcript.retain(|_| keep.next().unwrap_or(false));
+        response.total_matches = Some(total);
+        response.truncated = Some(response.truncated.unwrap_or(false) || total > max);
+    }
+    // Cached views are English-abbreviated; re-render only when asked otherwise
+    if let Some(count) = response.view_count
+        && (payload.locale.is_some() || payload.view_format != ViewFormat::Abbreviated)
+    {
+        let locale = number_locale(payload.locale.as_deref());
+        response.views = format_view_count(count, payload.view_format, locale);
+    }
+    if payload.mask_profanity
+        && rewrite_text(&mut response.transcript, |text| {
+            mask_profanity(text, &state.profanity)
+        })
+    {
+        applied.push("mask_profanity");
+    }
+    let case_changed = match payload.text_case {
+        TextCase::Original => false,
+        TextCase::Lower => rewrite_text(&mut response.transcript, str::to_lowercase),
+        TextCase::Upper => rewrite_text(&mut response.transcript, str::to_uppercase),
+    };
+    if case_changed {
+        applied.push("text_case");
+    }
+    if payload.timestamp_format != TimestampFormat::Auto {
+        let format = payload.timestamp_format;
+        for snippet in &mut response.transcript {
+            snippet.start = format_timestamp(snippet.start_seconds, format);
+            snippet.end = format_timestamp(snippet.start_seconds + snippet.duration, format);
+        }
+        for cue in response.sounds.iter_mut().flatten() {
+            cue.start = format_timestamp(cue.start_seconds, format);
+        }
+        response.duration = response
+            .duration_seconds
+            .map(|secs| format_timestamp(secs as f64, format));
+    }
+    if payload.include_meta {
+        let track = if response.generated {
+            TrackKind::AutoGenerated
+        } else {
+            TrackKind::Manual
+        };
+        for snippet in &mut response.transcript {
+            snippet.meta = Some(SnippetMeta {
+                track,
+                speaker_change: snippet.speaker_change,
+            });
+        }
+    }
+    if payload.include_links {
+        let base = canonical_url(&response.id);
+        for snippet in &mut response.transcript {
+            // Floored, so the link never lands after the caption starts
+            snippet.link = Some(format!("{}?t={}", base, snippet.start_seconds as u64));
+        }
+    }
+    if payload.include_index {
+        for snippet in &mut response.transcript {
+            snippet.index = Some(snippet.position);
+        }
+    }
+    // Paginate last so the window is over the fully filtered transcript
+    if payload.offset.is_some() || payload.limit.is_some() {
+        let total = response.transcript.len();
+        let offset = payload.offset.unwrap_or(0).min(total);
+        let end = payload
+            .limit
+            .map_or(total, |limit| offset.saturating_add(limit).min(total));
+        response.transcript.truncate(end);
+        response.transcript.drain(..offset);
+        response.total = Some(total);
+        response.has_more = Some(end < total);
+    }
+    response.transformations = applied;
+}
+
+#[utoipa::path(
+    post,
+    path = "/transcript",
+    request_body = YTRequest,
+    responses(
+        (status = 200, description = "Transcript in the requested format", content(
+            (YTResponse = "application/json"),
+            (String = "text/plain"),
+            (String = "text/vtt"),
+            (String = "text/csv"),
+            (String = "text/markdown"),
+            (String = "text/html"),
+            (String = "application/x-ndjson")
+        )),
+        (status = 400, description = "Malformed body, disabled format or untranslatable track", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = ErrorBody),
+        (status = 403, description = "Video is unplayable, age-restricted or otherwise blocked", body = ErrorBody),
+        (status = 404, description = "Video or transcript not found", body = ErrorBody),
+        (status = 413, description = "Body too large, or video is longer than MAX_VIDEO_DURATION_SECS", body = ErrorBody),
+        (status = 422, description = "Request failed validation", body = ErrorBody),
+        (status = 429, description = "Rate limit exceeded", body = ErrorBody),
+        (status = 500, description = "Fetching from YouTube failed", body = ErrorBody),
+        (status = 504, description = "YouTube did not respond in time", body = ErrorBody)
+    )
+)]
+async fn yt(
+    State(state): State<Arc<AppState>>,
+    AcceptFormat(accepted): AcceptFormat,
+    AcceptLanguage(accept_languages): AcceptLanguage,
+    payload: Result<Json<YTRequest>, JsonRejection>,
+) -> Result<Response, ApiError> {
+    let Json(mut payload) = payload?;
+    // Multi is JSON-only; a browser-style Accept shouldn't turn that into a
+    // rejection, so only an explicit `format` is checked against it
+    if !payload.multi {
+        payload.format = payload.format.or(state.negotiated_format(accepted));
+    }
+    payload.accept_languages = accept_languages;
+    let (rendered, cache_hit) = transcript(&state, &payload).await?;
+    Ok(finish_transcript_response(rendered, cache_hit, None))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct TranscriptQuery {
+    url: Option<String>,
+    format: Option<OutputFormat>,
+    // Comma-separated priority list, e.g. `language=es,en`
+    language: Option<String>,
+    start_after: Option<f64>,
+    end_before: Option<f64>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    #[serde(default)]
+    text_case: TextCase,
+    #[serde(default)]
+    pretty: bool,
+}
+
+impl TranscriptQuery {
+    fn into_request(self, video_id: Option<String>) -> YTRequest {
+        YTRequest {
+            video_id,
+            video_url: self.url,
+            format: self.format,
+            languages: self
+                .language
+                .map(|l| l.split(',').map(|c| c.trim().to_string()).collect()),
+            start_after: self.start_after,
+            end_before: self.end_before,
+            offset: self.offset,
+            limit: self.limit,
+            text_case: self.text_case,
+            pretty: self.pretty,
+            ..Default::default()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/transcript/{video_id}",
+    params(("video_id" = String, Path, description = "11-character YouTube video ID"), TranscriptQuery),
+    responses(
+        (status = 200, description = "Transcript in the requested format", content(
+            (YTResponse = "application/json"),
+            (String = "text/plain"),
+            (String = "text/vtt"),
+            (String = "text/csv"),
+            (String = "text/markdown"),
+            (String = "text/html"),
+            (String = "application/x-ndjson")
+        )),
+        (status = 304, description = "Matches If-None-Match"),
+        (status = 400, description = "Bad query, disabled format or untranslatable track", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = ErrorBody),
+        (status = 403, description = "Video is unplayable, age-restricted or otherwise blocked", body = ErrorBody),
+        (status = 404, description = "Video or transcript not found", body = ErrorBody),
+        (status = 413, description = "Video is longer than MAX_VIDEO_DURATION_SECS", body = ErrorBody),
+        (status = 422, description = "Request failed validation", body = ErrorBody),
+        (status = 429, description = "Rate limit exceeded", body = ErrorBody),
+        (status = 500, description = "Fetching from YouTube failed", body = ErrorBody),
+        (status = 504, description = "YouTube did not respond in time", body = ErrorBody)
+    )
+)]
+async fn yt_get(
+    State(state): State<Arc<AppState>>,
+    Path(video_id): Path<String>,
+    AcceptFormat(accepted): AcceptFormat,
+    AcceptLanguage(accept_languages): AcceptLanguage,
+    query: Result<Query<TranscriptQuery>, QueryRejection>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let Query(query) = query?;
+    let mut payload = query.into_request(Some(video_id));
+    payload.format = payload.format.or(state.negotiated_format(accepted));
+    payload.accept_languages = accept_languages;
+    transcript_get(&state, payload, &headers).await
+}
+
+// GET /transcript/{video_id}/stream: one `snippet` event per caption, then `done`
+#[utoipa::path(
+    get,
+    path = "/transcript/{video_id}/stream",
+    params(("video_id" = String, Path, description = "11-character YouTube video ID"), TranscriptQuery),
+    responses(
+        (status = 200, description = "One `snippet` event per caption, then `done`", content_type = "text/event-stream"),
+        (status = 400, description = "Bad query or untranslatable track", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = ErrorBody),
+        (status = 403, description = "Video is unplayable, age-restricted or otherwise blocked", body = ErrorBody),
+        (status = 404, description = "Video or transcript not found", body = ErrorBody),
+        (status = 413, description = "Video is longer than MAX_VIDEO_DURATION_SECS", body = ErrorBody),
+        (status = 422, description = "Request failed validation", body = ErrorBody),
+        (status = 429, description = "Rate limit exceeded", body = ErrorBody),
+        (status = 500, description = "Fetching from YouTube failed", body = ErrorBody),
+        (status = 504, description = "YouTube did not respond in time", body = ErrorBody)
+    )
+)]
+async fn yt_stream(
+    State(state): State<Arc<AppState>>,
+    Path(video_id): Path<String>,
+    AcceptLanguage(accept_languages): AcceptLanguage,
+    query: Result<Query<TranscriptQuery>, QueryRejection>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Query(query) = query?;
+    let mut payload = query.into_request(Some(video_id));
+    payload.accept_languages = accept_languages;
+    let (response, _) = prepare_transcript(&state, &payload).await?;
+
+    let events = stream::iter(response.transcript)
+        .map(|snippet| Event::default().event("snippet").json_data(snippet))
+        .chain(stream::once(async {
+            Ok(Event::default().event("done").data(""))
+        }));
+    // Events must reach the client as they're written: no caching, and no
+    // buffering by reverse proxies that honor X-Accel-Buffering
+    Ok((
+        [
+            (header::CACHE_CONTROL, "no-cache"),
+            (HeaderName::from_static("x-accel-buffering"), "no"),
+        ],
+        Sse::new(events).keep_alive(KeepAlive::default()),
+    ))
+}
+
+// GET /transcript?url=...
+#[utoipa::path(
+    get,
+    path = "/transcript",
+    params(TranscriptQuery),
+    responses(
+        (status = 200, description = "Transcript in the requested format", content(
+            (YTResponse = "application/json"),
+            (String = "text/plain"),
+            (String = "text/vtt"),
+            (String = "text/csv"),
+            (String = "text/markdown"),
+            (String = "text/html"),
+            (String = "application/x-ndjson")
+        )),
+        (status = 400, description = "Bad query, disabled format or untranslatable track", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = ErrorBody),
+        (status = 403, description = "Video is unplayable, age-restricted or otherwise blocked", body = ErrorBody),
+        (status = 404, description = "Video or transcript not found", body = ErrorBody),
+        (status = 413, description = "Video is longer than MAX_VIDEO_DURATION_SECS", body = ErrorBody),
+        (status = 422, description = "Request failed validation", body = ErrorBody),
+        (status = 429, description = "Rate limit exceeded", body = ErrorBody),
+        (status = 500, description = "Fetching from YouTube failed", body = ErrorBody),
+        (status = 504, description = "YouTube did not respond in time", body = ErrorBody)
+    )
+)]
+async fn yt_get_by_url(
+    State(state): State<Arc<AppState>>,
+    AcceptFormat(accepted): AcceptFormat,
+    AcceptLanguage(accept_languages): AcceptLanguage,
+    query: Result<Query<TranscriptQuery>, QueryRejection>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let Query(query) = query?;
+    let mut payload = query.into_request(None);
+    payload.format = payload.format.or(state.negotiated_format(accepted));
+    payload.accept_languages = accept_languages;
+    transcript_get(&state, payload, &headers).await
+}
+
+async fn transcript_get(
+    state: &AppState,
+    payload: YTRequest,
+    headers: &HeaderMap,
+) -> Result<Response, ApiError> {
+    let (rendered, cache_hit) = transcript(state, &payload).await?;
+    let if_none_match = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok());
+    Ok(finish_transcript_response(
+        rendered,
+        cache_hit,
+        if_none_match,
+    ))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct VideoRequest {
+    video_id: Option<String>,
+    video_url: Option<String>,
+}
+
+// POST /video: metadata only, skipping the transcript fetch
+#[utoipa::path(
+    post,
+    path = "/video",
+    request_body = VideoRequest,
+    responses(
+        (status = 200, description = "Video details", body = VideoDetailsResponse),
+        (status = 400, description = "Malformed request body", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = ErrorBody),
+        (status = 403, description = "Video is unplayable, age-restricted or otherwise blocked", body = ErrorBody),
+        (status = 404, description = "Video or transcript not found", body = ErrorBody),
+        (status = 413, description = "Request body too large", body = ErrorBody),
+        (status = 422, description = "Request failed validation", body = ErrorBody),
+        (status = 429, description = "Rate limit exceeded", body = ErrorBody),
+        (status = 500, description = "Fetching from YouTube failed", body = ErrorBody),
+        (status = 504, description = "YouTube did not respond in time", body = ErrorBody)
+    )
+)]
+async fn video(
+    State(state): State<Arc<AppState>>,
+    payload: Result<Json<VideoRequest>, JsonRejection>,
+) -> Result<Json<VideoDetailsResponse>, ApiError> {
+    let Json(payload) = payload?;
+    let video_id = resolve_video_id(payload.video_id.as_deref(), payload.video_url.as_deref())?;
+    video_details(&state, video_id).await.map(Json)
+}
+
+// GET /video/{video_id}
+#[utoipa::path(
+    get,
+    path = "/video/{video_id}",
+    params(("video_id" = String, Path, description = "11-character YouTube video ID")),
+    responses(
+        (status = 200, description = "Video details", body = VideoDetailsResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorBody),
+        (status = 403, description = "Video is unplayable, age-restricted or otherwise blocked", body = ErrorBody),
+        (status = 404, description = "Video or transcript not found", body = ErrorBody),
+        (status = 422, description = "Request failed validation", body = ErrorBody),
+        (status = 429, description = "Rate limit exceeded", body = ErrorBody),
+        (status = 500, description = "Fetching from YouTube failed", body = ErrorBody),
+        (status = 504, description = "YouTube did not respond in time", body = ErrorBody)
+    )
+)]
+async fn video_get(
+    State(state): State<Arc<AppState>>,
+    Path(video_id): Path<String>,
+) -> Result<Json<VideoDetailsResponse>, ApiError> {
+    let video_id = resolve_video_id(Some(&video_id), None)?;
+    video_details(&state, video_id).await.map(Json)
+}
+
+// POST /video/meta: details plus the description and tags
+#[utoipa::path(
+    post,
+    path = "/video/meta",
+    request_body = VideoRequest,
+    responses(
+        (status = 200, description = "Video details with description and tags", body = VideoMetaResponse),
+        (status = 400, description = "Malformed request body", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = ErrorBody),
+        (status = 403, description = "Video is unplayable, age-restricted or otherwise blocked", body = ErrorBody),
+        (status = 404, description = "Video or transcript not found", body = ErrorBody),
+        (status = 413, description = "Request body too large", body = ErrorBody),
+        (status = 422, description = "Request failed validation", body = ErrorBody),
+        (status = 429, description = "Rate limit exceeded", body = ErrorBody),
+        (status = 500, description = "Fetching from YouTube failed", body = ErrorBody),
+        (status = 504, description = "YouTube did not respond in time", body = ErrorBody)
+    )
+)]
+async fn video_meta(
+    State(state): State<Arc<AppState>>,
+    payload: Result<Json<VideoRequest>, JsonRejection>,
+) -> Result<Json<VideoMetaResponse>, ApiError> {
+    let Json(payload) = payload?;
+    let video_id = resolve_video_id(payload.video_id.as_deref(), payload.video_url.as_deref())?;
+    let details = video_details(&state, video_id).await?;
+    Ok(Json(details.into()))
+}
+
+// GET /video/{video_id}/meta
+#[utoipa::path(
+    get,
+    path = "/video/{video_id}/meta",
+    params(("video_id" = String, Path, description = "11-character YouTube video ID")),
+    responses(
+        (status = 200, description = "Video details with description and tags", body = VideoMetaResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorBody),
+        (status = 403, description = "Video is unplayable, age-restricted or otherwise blocked", body = ErrorBody),
+        (status = 404, description = "Video or transcript not found", body = ErrorBody),
+        (status = 422, description = "Request failed validation", body = ErrorBody),
+        (status = 429, description = "Rate limit exceeded", body = ErrorBody),
+        (status = 500, description = "Fetching from YouTube failed", body = ErrorBody),
+        (status = 504, description = "YouTube did not respond in time", body = ErrorBody)
+    )
+)]
+async fn video_meta_get(
+    State(state): State<Arc<AppState>>,
+    Path(video_id): Path<String>,
+) -> Result<Json<VideoMetaResponse>, ApiError> {
+    let video_id = resolve_video_id(Some(&video_id), None)?;
+    let details = video_details(&state, video_id).await?;
+    Ok(Json(details.into()))
+}
+
+async fn video_details(
+    state: &AppState,
+    video_id: String,
+) -> Result<VideoDetailsResponse, ApiError> {
+    Span::current().record("video_id", video_id.as_str());
+    with_failover(state, |client| {
+        let video_id = video_id.clone();
+        async move { fetch_details(&client, &video_id, Instant::now() + fetch_timeout()).await }
+    })
+    .await
+}
+
+#[derive(Deserialize, ToSchema)]
+struct LanguagesRequest {
+    video_id: Option<String>,
+    video_url: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct LanguagesResponse {
+    id: String,
+    languages: Vec<TranscriptLanguage>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct TranscriptLanguage {
+    code: String,
+    name: String,
+    generated: bool,
+    translatable: bool,
+}
+
+#[utoipa::path(
+    post,
+    path = "/transcript/languages",
+    request_body = LanguagesRequest,
+    responses(
+        (status = 200, description = "Available caption tracks", body = LanguagesResponse),
+        (status = 400, description = "Malformed request body", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = ErrorBody),
+        (status = 403, description = "Video is unplayable, age-restricted or otherwise blocked", body = ErrorBody),
+        (status = 404, description = "Video or transcript not found", body = ErrorBody),
+        (status = 413, description = "Request body too large", body = ErrorBody),
+        (status = 422, description = "Request failed validation", body = ErrorBody),
+        (status = 429, description = "Rate limit exceeded", body = ErrorBody),
+        (status = 500, description = "Fetching from YouTube failed", body = ErrorBody),
+        (status = 504, description = "YouTube did not respond in time", body = ErrorBody)
+    )
+)]
+async fn languages(
+    State(state): State<Arc<AppState>>,
+    payload: Result<Json<LanguagesRequest>, JsonRejection>,
+) -> Result<Json<LanguagesResponse>, ApiError> {
+    let Json(payload) = payload?;
+    let video_id = resolve_video_id(payload.video_id.as_deref(), payload.video_url.as_deref())?;
+    let proxy = state.proxies.next();
+    let client = state.proxies.client(proxy);
+
+    let list = with_deadline(Instant::now() + fetch_timeout(), "transcript list", async {
+        with_retry("transcript list", || client.api.list_transcripts(&video_id))
+            .await
+            .map_err(|e| transcript_error(e, &client))
+    })
+    .await;
+    if let Some(index) = proxy {
+        Span::current().record("proxy_index", index);
+        match &list {
+            Err(e) if e.is_upstream_failure() => state.proxies.record_failure(index),
+            _ => state.proxies.record_success(index),
+        }
+    }
+    let list = list?;
+
+    // Manually created tracks first, then auto-generated, each sorted by code
+    let mut languages: Vec<TranscriptLanguage> = list
+        .transcripts()
+        .map(|t| TranscriptLanguage {
+            code: t.language_code().to_string(),
+            name: t.language().to_string(),
+            generated: t.is_generated(),
+            translatable: t.is_translatable(),
+        })
+        .collect();
+    languages.sort_by(|a, b| (a.generated, &a.code).cmp(&(b.generated, &b.code)));
+
+    Ok(Json(LanguagesResponse {
+        id: video_id,
+        languages,
+    }))
+}
+
+#[derive(Serialize, ToSchema)]
+struct ValidateResponse {
+    valid: bool,
+    video_id: Option<String>,
+    error: Option<String>,
+    code: Option<&'static str>,
+}
+
+// POST /validate: the same checks as the transcript endpoints, without
+// touching YouTube. Invalid input is a 200 with `valid: false`
+#[utoipa::path(
+    post,
+    path = "/validate",
+    request_body = VideoRequest,
+    responses(
+        (status = 200, description = "Whether the input names a video", body = ValidateResponse),
+        (status = 400, description = "Malformed request body", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = ErrorBody),
+        (status = 429, description = "Rate limit exceeded", body = ErrorBody)
+    )
+)]
+async fn validate(
+    payload: Result<Json<VideoRequest>, JsonRejection>,
+) -> Result<Json<ValidateResponse>, ApiError> {
+    let Json(payload) = payload?;
+    let response = match resolve_video_id(payload.video_id.as_deref(), payload.video_url.as_deref())
+    {
+        Ok(video_id) => ValidateResponse {
+            valid: true,
+            video_id: Some(video_id),
+            error: None,
+            code: None,
+        },
+        Err(e) => ValidateResponse {
+            valid: false,
+            video_id: None,
+            error: Some(e.message),
+            code: Some(e.code),
+        },
+    };
+    Ok(Json(response))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct PlaylistRequest {
+    playlist_id: Option<String>,
+    playlist_url: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct PlaylistResponse {
+    id: String,
+    video_ids: Vec<String>,
+}
+
+fn resolve_playlist_id(
+    playlist_id: Option<&str>,
+    playlist_url: Option<&str>,
+) -> Result<String, ApiError> {
+    let id = match (playlist_id, playlist_url) {
+        (Some(_), Some(_)) => {
+            return Err(ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "conflicting_playlist_input",
+                "Cannot provide both playlist_id and playlist_url. Use one or the other.",
+            ));
+        }
+        (None, None) => {
+            return Err(ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "missing_playlist_input",
+                "Must provide either playlist_id or playlist_url.",
+            ));
+        }
+        (Some(id), None) => id.trim(),
+        (None, Some(url)) => query_param(url.trim(), "list").ok_or_else(|| {
+            ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_playlist_url",
+                "playlist_url must carry a list= parameter.",
+            )
+        })?,
+    };
+
+    validate_playlist_id(id).map_err(validation_error("invalid_playlist_id"))?;
+    Ok(id.to_string())
+}
+
+// The library has no playlist support, so this scrapes the playlist page's
+// initial data. That only covers the first page (about 100 videos)
+async fn fetch_playlist(client: &YtClient, playlist_id: &str) -> Result<Vec<String>, ApiError> {
+    let upstream_error = |e: reqwest::Error| {
+        ApiError::new(
+            StatusCode::BAD_GATEWAY,
+            "playlist_fetch_failed",
+            format!("Playlist error (proxy={}): {}", client.has_proxy, e),
+        )
+    };
+    let html = client
+        .http
+        .get("https://www.youtube.com/playlist")
+        .query(&[("list", playlist_id)])
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(upstream_error)?
+        .text()
+        .await
+        .map_err(upstream_error)?;
+
+    const MARKER: &str = "\"playlistVideoRenderer\":{\"videoId\":\"";
+    let mut video_ids: Vec<String> = Vec::new();
+    for (i, _) in html.match_indices(MARKER) {
+        let id = html[i + MARKER.len()..]
+            .split('"')
+            .next()
+            .unwrap_or_default();
+        if validate_video_id(id).is_ok() && !video_ids.iter().any(|v| v == id) {
+            video_ids.push(id.to_string());
+        }
+    }
+    Ok(video_ids)
+}
+
+// POST /playlist: resolves a playlist to its video IDs for use with /transcript/batch
+#[utoipa::path(
+    post,
+    path = "/playlist",
+    request_body = PlaylistRequest,
+    responses(
+        (status = 200, description = "Video IDs in playlist order", body = PlaylistResponse),
+        (status = 400, description = "Malformed request body", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = ErrorBody),
+        (status = 404, description = "Playlist is private, empty or missing", body = ErrorBody),
+        (status = 413, description = "Request body too large", body = ErrorBody),
+        (status = 422, description = "Request failed validation", body = ErrorBody),
+        (status = 429, description = "Rate limit exceeded", body = ErrorBody),
+        (status = 502, description = "YouTube could not be reached", body = ErrorBody),
+        (status = 504, description = "YouTube did not respond in time", body = ErrorBody)
+    )
+)]
+async fn playlist(
+    State(state): State<Arc<AppState>>,
+    payload: Result<Json<PlaylistRequest>, JsonRejection>,
+) -> Result<Json<PlaylistResponse>, ApiError> {
+    let Json(payload) = payload?;
+    let playlist_id = resolve_playlist_id(
+        payload.playlist_id.as_deref(),
+        payload.playlist_url.as_deref(),
+    )?;
+
+    let video_ids = with_failover(&state, |client| {
+        let playlist_id = playlist_id.clone();
+        async move {
+            let deadline = Instant::now() + fetch_timeout();
+            with_deadline(deadline, "playlist", fetch_playlist(&client, &playlist_id)).await
+        }
+    })
+    .await?;
+
+    // YouTube serves an error page without videos for private or missing lists
+    if video_ids.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "playlist_unavailable",
+            "Playlist is private, empty or does not exist.",
+        ));
+    }
+
+    Ok(Json(PlaylistResponse {
+        id: playlist_id,
+        video_ids,
+    }))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct VideoInput {
+    video_id: Option<String>,
+    video_url: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct DiffRequest {
+    a: VideoInput,
+    b: VideoInput,
+    language: Option<String>,
+    languages: Option<Vec<String>>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct DiffResponse {
+    a: DiffSide,
+    b: DiffSide,
+    // Over the sets of distinct words
+    jaccard: f64,
+    // Over word frequencies
+    cosine: f64,
+    equal_tokens: usize,
+    removed_tokens: usize,
+    added_tokens: usize,
+    // Set when the transcripts were too far apart for a full diff, so the
+    // differing middle is reported as one removal and one addition
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    approximate: bool,
+    // Only the runs that differ, in transcript order
+    segments: Vec<DiffSegment>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct DiffSide {
+    id: String,
+    title: String,
+    token_count: usize,
+    // Text transforms applied before diffing, as in YTResponse
+    transformations: Vec<&'static str>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct DiffSegment {
+    op: DiffOp,
+    // Token offset into the side the text came from
+    offset: usize,
+    text: String,
+    #[serde(skip)]
+    tokens: usize,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum DiffOp {
+    Removed,
+    Added,
+}
+
+// Caps the LCS table (4 bytes a cell) at 16 MB per request; larger middles
+// fall back to an approximate diff
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+// Lowercased words with surrounding punctuation trimmed, so casing and caption
+// punctuation differences don't count as edits
+fn diff_tokens(snippets: &[TranscriptSnippet]) -> Vec<String> {
+    snippets
+        .iter()
+        .flat_map(|s| s.text.split_whitespace())
+        .map(|word| {
+            word.trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase()
+        })
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+fn jaccard_similarity(a: &[String], b: &[String]) -> f64 {
+    let a: HashSet<&str> = a.iter().map(String::as_str).collect();
+    let b: HashSet<&str> = b.iter().map(String::as_str).collect();
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+fn word_counts(tokens: &[String]) -> HashMap<&str, f64> {
+    let mut counts: HashMap<&str, f64> = HashMap::new();
+    for token in tokens {
+        *counts.entry(token.as_str()).or_default() += 1.0;
+    }
+    counts
+}
+
+fn cosine_similarity(a: &[String], b: &[String]) -> f64 {
+    let (a, b) = (word_counts(a), word_counts(b));
+    let norm = |counts: &HashMap<&str, f64>| counts.values().map(|c| c * c).sum::<f64>().sqrt();
+    let denominator = norm(&a) * norm(&b);
+    if denominator == 0.0 {
+        return if a.is_empty() && b.is_empty() {
+            1.0
+        } else {
+            0.0
+        };
+    }
+    let dot: f64 = a
+        .iter()
+        .filter_map(|(token, count)| b.get(token).map(|other| count * other))
+        .sum();
+    dot / denominator
+}
+
+fn round_similarity(value: f64) -> f64 {
+    (value * 10_000.0).round() / 10_000.0
+}
+
+// Word-level LCS diff. The common prefix and suffix are trimmed first since
+// re-uploads usually differ in only a few places; returns the differing runs
+// and whether the result is exact
+fn diff_words(a: &[String], b: &[String]) -> (Vec<DiffSegment>, bool) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (mid_a, mid_b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut segments: Vec<DiffSegment> = Vec::new();
+    let mut push = |op: DiffOp, offset: usize, token: &str| match segments.last_mut() {
+        Some(last) if last.op == op && last.offset + last.tokens == offset => {
+            last.text.push(' ');
+            last.text.push_str(token);
+            last.tokens += 1;
+        }
+        _ => segments.push(DiffSegment {
+            op,
+            offset,
+            text: token.to_string(),
+            tokens: 1,
+        }),
+    };
+
+    let (n, m) = (mid_a.len(), mid_b.len());
+    if (n + 1).saturating_mul(m + 1) > MAX_DIFF_CELLS {
+        for (i, token) in mid_a.iter().enumerate() {
+            push(DiffOp::Removed, prefix + i, token);
+        }
+        for (j, token) in mid_b.iter().enumerate() {
+            push(DiffOp::Added, prefix + j, token);
+        }
+        return (segments, false);
+    }
+
+    // lcs[i][j]: LCS length of mid_a[i..] and mid_b[j..]
+    let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+    let at = |i: usize, j: usize| i * (m + 1) + j;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[at(i, j)] = if mid_a[i] == mid_b[j] {
+                lcs[at(i + 1, j + 1)] + 1
+            } else {
+                lcs[at(i + 1, j)].max(lcs[at(i, j + 1)])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && mid_a[i] == mid_b[j] {
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && lcs[at(i + 1, j)] >= lcs[at(i, j + 1)]) {
+            push(DiffOp::Removed, prefix + i, &mid_a[i]);
+            i += 1;
+        } else {
+            push(DiffOp::Added, prefix + j, &mid_b[j]);
+            j += 1;
+        }
+    }
+    (segments, true)
+}
+
+// POST /transcript/diff: compares two videos' transcripts word by word
+#[utoipa::path(
+    post,
+    path = "/transcript/diff",
+    request_body = DiffRequest,
+    responses(
+        (status = 200, description = "Similarity scores and differing segments", body = DiffResponse),
+        (status = 400, description = "Malformed request body", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = ErrorBody),
+        (status = 403, description = "Video is unplayable, age-restricted or otherwise blocked", body = ErrorBody),
+        (status = 404, description = "Video or transcript not found", body = ErrorBody),
+        (status = 413, description = "Body too large, or either video is longer than MAX_VIDEO_DURATION_SECS", body = ErrorBody),
+        (status = 422, description = "Request failed validation", body = ErrorBody),
+        (status = 429, description = "Rate limit exceeded", body = ErrorBody),
+        (status = 500, description = "Fetching from YouTube failed", body = ErrorBody),
+        (status = 504, description = "YouTube did not respond in time", body = ErrorBody)
+    )
+)]
+async fn diff(
+    State(state): State<Arc<AppState>>,
+    payload: Result<Json<DiffRequest>, JsonRejection>,
+) -> Result<Json<DiffResponse>, ApiError> {
+    let Json(payload) = payload?;
+    let id_a = resolve_video_id(
+        payload.a.video_id.as_deref(),
+        payload.a.video_url.as_deref(),
+    )?;
+    let id_b = resolve_video_id(
+        payload.b.video_id.as_deref(),
+        payload.b.video_url.as_deref(),
+    )?;
+    let options = FetchOptions {
+        languages: resolve_languages(
+            payload.language.as_deref(),
+            payload.languages.as_deref(),
+            &state.default_languages,
+        )?,
+        translate_to: None,
+        prefer_manual: true,
+        use_proxy: true,
+        max_duration: state.max_duration(false),
+        require_details: true,
+    };
+
+    let (a, b) = future::join(
+        fetch_video_cached(&state, id_a, &options),
+        fetch_video_cached(&state, id_b, &options),
+    )
+    .await;
+    let (mut a, mut b) = (a?.0, b?.0);
+    // Default cleanup, so entity and whitespace noise doesn't count as edits
+    for response in [&mut a, &mut b] {
+        apply_transcript_options(&state, &YTRequest::default(), response);
+    }
+
+    let (tokens_a, tokens_b) = (diff_tokens(&a.transcript), diff_tokens(&b.transcript));
+    let (segments, exact) = diff_words(&tokens_a, &tokens_b);
+    let count = |op: DiffOp| -> usize {
+        segments
+            .iter()
+            .filter(|s| s.op == op)
+            .map(|s| s.tokens)
+            .sum()
+    };
+    let (removed_tokens, added_tokens) = (count(DiffOp::Removed), count(DiffOp::Added));
+
+    Ok(Json(DiffResponse {
+        jaccard: round_similarity(jaccard_similarity(&tokens_a, &tokens_b)),
+        cosine: round_similarity(cosine_similarity(&tokens_a, &tokens_b)),
+        equal_tokens: tokens_a.len() - removed_tokens,
+        removed_tokens,
+        added_tokens,
+        approximate: !exact,
+        segments,
+        a: DiffSide {
+            id: a.id,
+            title: a.title,
+            token_count: tokens_a.len(),
+            transformations: a.transformations,
+        },
+        b: DiffSide {
+            id: b.id,
+            title: b.title,
+            token_count: tokens_b.len(),
+            transformations: b.transformations,
+        },
+    }))
+}
+
+const MAX_BATCH_SIZE: usize = 50;
+const BATCH_CONCURRENCY: usize = 5;
+
+#[derive(Deserialize, ToSchema)]
+struct BatchRequest {
+    #[serde(default)]
+    video_ids: Vec<String>,
+    #[serde(default)]
+    video_urls: Vec<String>,
+    language: Option<String>,
+    languages: Option<Vec<String>>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct BatchItem {
+    input: String,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<YTResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/transcript/batch",
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "One item per input, in order", body = Vec<BatchItem>),
+        (status = 400, description = "Malformed request body", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API key", body = ErrorBody),
+        (status = 413, description = "Request body too large", body = ErrorBody),
+        (status = 422, description = "Request failed validation", body = ErrorBody),
+        (status = 429, description = "Rate limit exceeded", body = ErrorBody)
+    )
+)]
+async fn batch(
+    State(state): State<Arc<AppState>>,
+    payload: Result<Json<BatchRequest>, JsonRejection>,
+) -> Result<Json<Vec<BatchItem>>, ApiError> {
+    let Json(payload) = payload?;
+    let total = payload.video_ids.len() + payload.video_urls.len();
+    if total == 0 {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "empty_batch",
+            "Must provide at least one entry in video_ids or video_urls.",
+        ));
+    }
+    if total > MAX_BATCH_SIZE {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "batch_too_large",
+            format!("Batch cannot contain more than {} videos.", MAX_BATCH_SIZE),
+        ));
+    }
+
+    let options = FetchOptions {
+        languages: resolve_languages(
+            payload.language.as_deref(),
+            payload.languages.as_deref(),
+            &state.default_languages,
+        )?,
+        translate_to: None,
+        prefer_manual: true,
+        use_proxy: true,
+        max_duration: state.max_duration(false),
+        require_details: true,
+    };
+    let options = &options;
+    let state = &state;
+
+    let inputs = payload
+        .video_ids
+        .into_iter()
+        .map(|id| (id, false))
+        .chain(payload.video_urls.into_iter().map(|url| (url, true)));
+
+    // Each item resolves independently so one bad ID doesn't fail the batch
+    let items = stream::iter(inputs)
+        .map(|(input, is_url)| async move {
+            let result = if is_url {
+                resolve_video_id(None, Some(&input))
+            } else {
+                resolve_video_id(Some(&input), None)
+            };
+            let result =
+                match result {
+                    Ok(video_id) => fetch_video_cached(state, video_id, options).await.map(
+                        |(mut response, _)| {
+                            // Batch takes no snippet options, so items get the
+                            // same defaults (and `transformations`) as a bare
+                            // single-video request
+                            apply_transcript_options(state, &YTRequest::default(), &mut response);
+                            response.chapters = None;
+                            response.input = Some(input.clone());
+                            response.canonical_url = Some(canonical_url(&response.id));
+                            if is_url {
+                                response.start_seconds = extract_start_from_url(&input);
+                            }
+                            response
+                        },
+                    ),
+                    Err(e) => Err(e),
+                };
+
+            match result {
+                Ok(response) => BatchItem {
+                    input,
+                    status: StatusCode::OK.as_u16(),
+                    result: Some(response),
+                    error: None,
+                    code: None,
+                },
+                Err(e) => BatchItem {
+                    input,
+                    status: e.status.as_u16(),
+                    result: None,
+                    error: Some(e.message),
+                    code: Some(e.code),
+                },
+            }
+        })
+        .buffered(BATCH_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+    Ok(Json(items))
+}
+
+// CORS policy: GET/POST (plus OPTIONS preflight) with any request header, exposing
+// the X-Cache and ETag response headers. Origins come from the comma-separated
+// CORS_ALLOWED_ORIGINS env var; unset or "*" allows any origin (dev default).
+// Applied to every route, the SSE stream included: an EventSource connection
+// is a plain GET, so it only needs the allow-origin header on the response.
+fn cors_layer() -> CorsLayer {
+    let origins: Vec<HeaderValue> = std::env::var("CORS_ALLOWED_ORIGINS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|o| !o.is_empty() && *o != "*")
+        .filter_map(|o| HeaderValue::from_str(o).ok())
+        .collect();
+
     let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
+        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+        .allow_headers(Any)
+        .expose_headers([
+            HeaderName::from_static("x-cache"),
+            HeaderName::from_static(REQUEST_ID_HEADER),
+            header::ETAG,
+        ]);
+
+    if origins.is_empty() {
+        cors.allow_origin(Any)
+    } else {
+        cors.allow_origin(origins)
+    }
+}
+
+fn api_keys_from_env() -> Vec<String> {
+    std::env::var("API_KEYS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+const DEFAULT_PROFANITY: &[&str] = &[
+    "fuck", "fucking", "fucked", "shit", "shitty", "bitch", "bastard", "asshole", "dick", "cunt",
+    "piss", "damn", "crap",
+];
+
+// PROFANITY_WORDS (comma-separated) replaces the default list entirely
+fn profanity_words_from_env() -> Vec<String> {
+    match std::env::var("PROFANITY_WORDS") {
+        Ok(words) => words
+            .split(',')
+            .map(|w| w.trim().to_lowercase())
+            .filter(|w| !w.is_empty())
+            .collect(),
+        Err(_) => DEFAULT_PROFANITY.iter().map(|w| w.to_string()).collect(),
+    }
+}
+
+// Accepts either `Authorization: Bearer <key>` or `X-API-Key: <key>`; with no
+// keys configured every request is allowed
+fn check_api_key(state: &AppState, headers: &HeaderMap) -> Result<(), ApiError> {
+    if state.api_keys.is_empty() {
+        return Ok(());
+    }
+
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-api-key").and_then(|v| v.to_str().ok()))
+        .map(str::trim);
+
+    match provided {
+        Some(key) if state.api_keys.iter().any(|k| k == key) => Ok(()),
+        _ => Err(ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+            "Missing or invalid API key.",
+        )),
+    }
+}
+
+async fn require_api_key(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    check_api_key(&state, request.headers())?;
+    Ok(next.run(request).await)
+}
+
+// We run behind Vercel's proxy, so the first X-Forwarded-For hop is the client
+fn client_ip(headers: &HeaderMap) -> String {
+    headers
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(',').next())
+        .or_else(|| headers.get("x-real-ip").and_then(|v| v.to_str().ok()))
+        .map(|ip| ip.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+async fn rate_limit(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let Some(limiter) = &state.rate_limiter else {
+        return next.run(request).await;
+    };
+
+    match limiter.check(&client_ip(request.headers())) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            let mut res = ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limited",
+                format!("Too many requests. Retry in {} seconds.", retry_after),
+            )
+            .into_response();
+            res.headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+            res
+        }
+    }
+}
+
+const DEFAULT_SHUTDOWN_GRACE_SECS: u64 = 25;
+
+// Counts requests in progress so shutdown can wait for them to finish
+#[derive(Default)]
+struct InFlight {
+    count: AtomicUsize,
+    draining: AtomicBool,
+    idle: Notify,
+}
+
+struct InFlightGuard<'a>(&'a InFlight);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+impl InFlight {
+    fn enter(&self) -> InFlightGuard<'_> {
+        self.count.fetch_add(1, Ordering::AcqRel);
+        InFlightGuard(self)
+    }
+
+    // Stops admitting requests, then waits up to `grace` for the in-flight ones;
+    // returns how many were still running when the window closed
+    async fn drain(&self, grace: Duration) -> usize {
+        self.draining.store(true, Ordering::Release);
+        let _ = tokio::time::timeout(grace, async {
+            loop {
+                let idle = self.idle.notified();
+                if self.count.load(Ordering::Acquire) == 0 {
+                    break;
+                }
+                idle.await;
+            }
+        })
+        .await;
+        self.count.load(Ordering::Acquire)
+    }
+}
+
+async fn track_in_flight(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // Keep-alive connections can still deliver requests while draining
+    if state.in_flight.draining.load(Ordering::Acquire) {
+        let mut res = ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "shutting_down",
+            "Server is shutting down, retry shortly.",
+        )
+        .into_response();
+        res.headers_mut()
+            .insert(header::CONNECTION, HeaderValue::from_static("close"));
+        return res;
+    }
+
+    let _guard = state.in_flight.enter();
+    next.run(request).await
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+}
+
+// Latency buckets in seconds; transcript fetches commonly take one to five
+const LATENCY_BUCKETS: &[f64] = &[0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 20.0];
+
+fn install_metrics() -> Result<PrometheusHandle, Error> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full("http_request_duration_seconds".to_string()),
+            LATENCY_BUCKETS,
+        )?
+        .install_recorder()?;
+
+    // Without the exporter's own HTTP listener, draining histograms is on us
+    let upkeep = handle.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(5));
+        loop {
+            interval.tick().await;
+            upkeep.run_upkeep();
+        }
+    });
+    Ok(handle)
+}
+
+// GET /metrics: Prometheus text exposition format. Scrapers authenticate
+// with the same API keys as everyone else
+async fn metrics(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
 
-    let router = Router::new()
+const REQUEST_ID_HEADER: &str = "x-request-id";
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+// Stored in request extensions by `propagate_request_id`
+#[derive(Clone)]
+struct RequestId(String);
+
+// Reuses the caller's X-Request-Id when it's a sane header value, otherwise
+// generates one, and echoes it on the response for cross-service tracing
+async fn propagate_request_id(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+        .map_or_else(|| Uuid::new_v4().to_string(), str::to_string);
+    request.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut res = next.run(request).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    res
+}
+
+// Wraps each request in a span carrying its request ID (the caller's, or one
+// generated by `propagate_request_id`); handlers fill in video_id, language,
+// cache and proxy_index, and the outcome is logged with its latency
+async fn log_requests(request: Request, next: Next) -> Response {
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map_or_else(String::new, |id| id.0.clone());
+    let span = info_span!(
+        "request",
+        request_id = %request_id,
+        method = %request.method(),
+        path = %request.uri().path(),
+        video_id = Empty,
+        language = Empty,
+        cache = Empty,
+        proxy_index = Empty,
+    );
+    let started = Instant::now();
+    // The route template keeps label cardinality bounded (no raw video IDs)
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched".to_string(), |path| path.as_str().to_string());
+    let method = request.method().to_string();
+
+    async move {
+        let res = next.run(request).await;
+        let status = res.status().as_u16();
+        let elapsed = started.elapsed();
+        let latency_ms = elapsed.as_millis() as u64;
+
+        let labels = [
+            ("method", method),
+            ("route", route),
+            ("status", status.to_string()),
+        ];
+        counter!("http_requests_total", &labels).increment(1);
+        histogram!("http_request_duration_seconds", &labels).record(elapsed.as_secs_f64());
+
+        if res.status().is_server_error() {
+            error!(status, latency_ms, "request failed");
+        } else if res.status().is_client_error() {
+            warn!(status, latency_ms, "request rejected");
+        } else {
+            info!(status, latency_ms, "request completed");
+        }
+        res
+    }
+    .instrument(span)
+    .await
+}
+
+fn app(state: Arc<AppState>) -> Router {
+    // Everything except the welcome route, probes, spec and favicon sits
+    // behind the API key check and rate limiter (rate limiting runs first).
+    // `/health?deep=true` checks the key itself
+    let api = Router::new()
+        .route("/transcript", post(yt).get(yt_get_by_url))
+        .route("/transcript/{video_id}", get(yt_get))
+        .route("/transcript/{video_id}/stream", get(yt_stream))
+        .route("/transcript/batch", post(batch))
+        .route("/transcript/diff", post(diff))
+        .route("/transcript/languages", post(languages))
+        .route("/playlist", post(playlist))
+        .route("/validate", post(validate))
+        .route("/video", post(video))
+        .route("/video/{video_id}", get(video_get))
+        .route("/video/meta", post(video_meta))
+        .route("/video/{video_id}/meta", get(video_meta_get))
+        .route("/metrics", get(metrics))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            require_api_key,
+        ))
+        .route_layer(middleware::from_fn_with_state(state.clone(), rate_limit));
+
+    Router::new()
         .route("/", get(hello))
-        .route("/transcript", post(yt))
+        .route("/health", get(health))
+        .route("/ready", get(ready))
+        .route("/openapi.json", get(openapi))
         .route("/favicon.ico", get(favicon))
-        .layer(cors);
+        .merge(api)
+        .layer(DefaultBodyLimit::max(MAX_BODY_BYTES))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            track_in_flight,
+        ))
+        .layer(middleware::from_fn(log_requests))
+        .layer(middleware::from_fn(propagate_request_id))
+        // Spelled out rather than relying on the default: SSE must never be
+        // compressed, since the encoder buffers events and stalls the
+        // stream. Images (the favicon) and tiny bodies are skipped too
+        .layer(
+            CompressionLayer::new().compress_when(
+                SizeAbove::new(32)
+                    .and(NotForContentType::GRPC)
+                    .and(NotForContentType::IMAGES)
+                    .and(NotForContentType::SSE),
+            ),
+        )
+        .layer(cors_layer())
+        .with_state(state)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    dotenv().ok();
+    
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+
+    let state = Arc::new(AppState {
+        cache: TranscriptCache::from_env(),
+        proxies: ProxyPool::from_env(cookie_jar_from_env()).map_err(|e| e.message)?,
+        rate_limiter: RateLimiter::from_env(),
+        api_keys: api_keys_from_env(),
+        profanity: profanity_words_from_env(),
+        default_languages: default_languages_from_env(),
+        allowed_formats: allowed_formats_from_env(),
+        max_video_duration: std::env::var("MAX_VIDEO_DURATION_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok()),
+        metrics: install_metrics()?,
+        in_flight: InFlight::default(),
+        ready: AtomicBool::new(false),
+    });
+    let shutdown_state = state.clone();
+
+    // Warm the direct client's connection pool (DNS, TLS) before reporting
+    // ready, so the first routed request doesn't pay the cold-start handshake.
+    // A failed warm-up is logged but doesn't keep the instance out of rotation
+    let warmup_state = state.clone();
+    tokio::spawn(async move {
+        let warmup = warmup_state
+            .proxies
+            .client(None)
+            .http
+            .head("https://www.youtube.com")
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await;
+        if let Err(e) = warmup {
+            warn!(error = %e, "client warm-up failed");
+        }
+        warmup_state.ready.store(true, Ordering::Release);
+        info!("ready to serve");
+    });
+
+    let router = app(state);
 
     let app = ServiceBuilder::new()
         .layer(VercelLayer::new())
         .service(router);
-    vercel_runtime::run(app).await
+
+    // The runtime has no shutdown hook: on SIGTERM/Ctrl-C stop accepting
+    // connections, then give in-flight fetches SHUTDOWN_GRACE_SECS to finish
+    tokio::select! {
+        result = vercel_runtime::run(app) => return result,
+        () = shutdown_signal() => {}
+    }
+
+    let grace = std::env::var("SHUTDOWN_GRACE_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_SHUTDOWN_GRACE_SECS);
+    info!(
+        grace_secs = grace,
+        "shutdown signal received, draining requests"
+    );
+    let remaining = shutdown_state
+        .in_flight
+        .drain(Duration::from_secs(grace))
+        .await;
+    if remaining > 0 {
+        warn!(
+            remaining,
+            "grace period elapsed with requests still running"
+        );
+    } else {
+        info!("all requests drained");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    fn snippet(start: f64, duration: f64, text: &str) -> TranscriptSnippet {
+        TranscriptSnippet {
+            start: seconds_to_timestamp(start),
+            start_seconds: start,
+            end: seconds_to_timestamp(start + duration),
+            duration,
+            text: text.to_string(),
+            chapter: None,
+            meta: None,
+            link: None,
+            index: None,
+            speaker_change: false,
+            position: 0,
+        }
+    }
+
+    #[test]
+    fn plain_text_of_empty_transcript_is_empty() {
+        assert_eq!(to_plain_text(&[], " "), "");
+        assert_eq!(to_plain_text(&[snippet(0.0, 1.0, "  ")], " "), "");
+    }
+
+    #[test]
+    fn plain_text_collapses_whitespace_and_honours_line_breaks() {
+        let snippets = [
+            snippet(0.0, 1.0, "hello   there"),
+            snippet(1.0, 1.0, ""),
+            snippet(2.0, 1.0, " general\tkenobi "),
+        ];
+        let spaced = YTRequest::default();
+        let broken = YTRequest {
+            line_breaks: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            to_plain_text(&snippets, spaced.text_separator()),
+            "hello there general kenobi"
+        );
+        assert_eq!(
+            to_plain_text(&snippets, broken.text_separator()),
+            "hello there\ngeneral kenobi"
+        );
+    }
+
+    #[test]
+    fn extracts_id_from_watch_url_variants() {
+        for url in [
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://m.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://music.youtube.com/watch?v=dQw4w9WgXcQ&feature=share",
+            "https://www.youtube.com/watch?list=PLx0sYbCqOb8TBPRdmBHs5Iftvv9TPboYG&v=dQw4w9WgXcQ",
+            "https://www.youtube.com/watch?feature=v=xyz&v=dQw4w9WgXcQ#t=10",
+        ] {
+            assert_eq!(
+                extract_id_from_url(url).ok().as_deref(),
+                Some("dQw4w9WgXcQ"),
+                "{url}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_watch_url_without_v_param() {
+        assert!(extract_id_from_url("https://m.youtube.com/watch?list=PL123").is_err());
+    }
+
+    #[test]
+    fn time_params_that_overflow_are_rejected() {
+        assert_eq!(parse_time_param("1h2m3s"), Some(3723));
+        assert_eq!(parse_time_param("9999999999999999h"), None);
+        assert_eq!(parse_time_param("18446744073709551615s1s"), None);
+        assert_eq!(
+            extract_start_from_url("https://youtu.be/dQw4w9WgXcQ?t=9999999999999999h"),
+            None
+        );
+
+        assert_eq!(parse_clock_timestamp("1:02:03"), Some(3723));
+        assert_eq!(parse_clock_timestamp("999999999999999999:00:00"), None);
+    }
+
+    fn part(start: f64, duration: f64, text: &str) -> FetchedTranscriptSnippet {
+        FetchedTranscriptSnippet {
+            text: text.to_string(),
+            start,
+            duration,
+        }
+    }
+
+    #[test]
+    fn end_rolls_over_into_hours_like_start() {
+        let crossing = to_snippet(0, &part(3598.5, 6.0, "late"));
+        assert_eq!(crossing.start, "59:58");
+        assert_eq!(crossing.end, "01:00:04");
+
+        let after = to_snippet(1, &part(3600.0, 5.0, "later"));
+        assert_eq!(after.start, "01:00:00");
+        assert_eq!(after.end, "01:00:05");
+    }
+
+    #[test]
+    fn unparseable_view_count_is_null_and_views_pass_through() {
+        let (views, view_count) = parse_views("No views");
+        let details = VideoDetailsResponse {
+            views,
+            view_count,
+            ..VideoDetailsResponse::unavailable("dQw4w9WgXcQ")
+        };
+        let json = serde_json::to_value(&details).unwrap();
+        assert_eq!(json["views"], "No views");
+        assert!(json["view_count"].is_null());
+
+        assert_eq!(
+            parse_views("1234567"),
+            ("1.2M".to_string(), Some(1_234_567))
+        );
+    }
+
+    #[test]
+    fn dedup_does_not_merge_into_an_empty_snippet() {
+        let mut snippets = vec![
+            snippet(0.0, 1.0, ""),
+            snippet(1.0, 1.0, "hello"),
+            snippet(2.0, 1.0, "hello world"),
+        ];
+        dedup_rolling(&mut snippets);
+
+        assert_eq!(snippets.len(), 2);
+        assert_eq!(snippets[0].text, "");
+        assert_eq!(snippets[1].text, "hello world");
+        assert_eq!(snippets[1].start_seconds, 1.0);
+        assert_eq!(snippets[1].end, "00:03");
+    }
+
+    #[test]
+    fn dedup_only_merges_on_word_boundaries() {
+        let mut snippets = vec![
+            snippet(0.0, 1.0, "no"),
+            snippet(1.0, 1.0, "nothing else"),
+            snippet(2.0, 1.0, "nothing else matters"),
+        ];
+        dedup_rolling(&mut snippets);
+
+        let texts: Vec<&str> = snippets.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, ["no", "nothing else matters"]);
+    }
+
+    #[test]
+    fn groups_thousands_per_locale() {
+        let de = number_locale(Some("de-DE"));
+        let fr = number_locale(Some("fr"));
+
+        assert_eq!(
+            format_view_count(1_234_567, ViewFormat::Full, de),
+            "1.234.567"
+        );
+        assert_eq!(
+            format_view_count(1_234_567, ViewFormat::Full, fr),
+            "1\u{202F}234\u{202F}567"
+        );
+        assert_eq!(format_views("1234567", de), "1,2 Mio.");
+        assert_eq!(format_views("1234567", fr), "1,2 M");
+        assert_eq!(
+            format_view_count(1_234_567, ViewFormat::Full, number_locale(Some("xx"))),
+            "1,234,567"
+        );
+    }
+
+    #[test]
+    fn decodes_html_entities() {
+        assert_eq!(decode_html_entities("it&#39;s"), "it's");
+        assert_eq!(decode_html_entities("salt &amp; pepper"), "salt & pepper");
+        assert_eq!(decode_html_entities("&#x27;hi&#X27;"), "'hi'");
+        // Decoded once only, and unknown or unterminated entities are kept
+        assert_eq!(decode_html_entities("&amp;lt;"), "&lt;");
+        assert_eq!(
+            decode_html_entities("R&D &bogus; a & b"),
+            "R&D &bogus; a & b"
+        );
+        assert_eq!(clean_text("  don&#39;t   stop&nbsp; "), "don't stop");
+    }
+
+    #[test]
+    fn only_a_leading_speaker_marker_is_stripped() {
+        let leading = to_snippet(0, &part(0.0, 1.0, ">> hello"));
+        assert_eq!(leading.text, "hello");
+        assert!(leading.speaker_change);
+
+        let mid_line = to_snippet(0, &part(0.0, 1.0, "a >> b"));
+        assert_eq!(mid_line.text, "a >> b");
+        assert!(!mid_line.speaker_change);
+    }
+
+    #[test]
+    fn extracts_only_short_closed_cues() {
+        assert_eq!(
+            extract_bracketed("[Music] hello (laughs) there"),
+            (
+                "hello there".to_string(),
+                vec!["Music".to_string(), "laughs".to_string()]
+            )
+        );
+        assert_eq!(
+            extract_bracketed("[upbeat [soft] music] ok"),
+            ("ok".to_string(), vec!["upbeat [soft] music".to_string()])
+        );
+        // A spoken aside isn't a sound cue
+        assert_eq!(
+            extract_bracketed("I said (and I meant it) no"),
+            ("I said (and I meant it) no".to_string(), Vec::new())
+        );
+        // Unclosed brackets keep the rest of the caption
+        assert_eq!(
+            extract_bracketed("wait (what happens next"),
+            ("wait (what happens next".to_string(), Vec::new())
+        );
+        assert_eq!(strip_bracketed("[Applause] thanks ["), "thanks [");
+    }
+
+    #[test]
+    fn diff_falls_back_to_an_approximate_diff_over_the_cell_cap() {
+        let words = |prefix: &str| -> Vec<String> {
+            std::iter::once("same".to_string())
+                .chain((0..2001).map(|i| format!("{prefix}{i}")))
+                .chain(std::iter::once("end".to_string()))
+                .collect()
+        };
+        let (a, b) = (words("a"), words("b"));
+        const { assert!(2002 * 2002 > MAX_DIFF_CELLS) };
+
+        let (segments, exact) = diff_words(&a, &b);
+        assert!(!exact);
+        assert_eq!(segments.len(), 2);
+        assert!(segments[0].op == DiffOp::Removed && segments[0].offset == 1);
+        assert!(segments[1].op == DiffOp::Added && segments[1].offset == 1);
+        assert_eq!(segments[0].tokens, 2001);
+
+        let (segments, exact) = diff_words(&a[..3], &b[..3]);
+        assert!(exact);
+        assert_eq!(segments.len(), 2);
+    }
+
+    #[test]
+    fn video_id_rejections() {
+        let rejection = |id: &str| {
+            let error = validate_video_id(id).unwrap_err();
+            (error.code.to_string(), error.message.unwrap().to_string())
+        };
+
+        assert!(validate_video_id("dQw4w9WgXcQ").is_ok());
+        assert_eq!(rejection("dQw4w9").0, "too_short");
+        assert_eq!(rejection("dQw4w9WgXcQQ").0, "too_long");
+        assert_eq!(rejection("dQw4w9WgX.Q").0, "invalid_characters");
+
+        let (code, message) = rejection("___________");
+        assert_eq!(code, "not_an_id");
+        assert!(message.contains("letter or digit"), "{message}");
+
+        let (code, message) = rejection("dQw4w9WgXcR");
+        assert_eq!(code, "not_an_id");
+        assert!(message.contains("copied exactly"), "{message}");
+    }
+
+    #[test]
+    fn multibyte_text_chunks_on_char_boundaries() {
+        let snippets = [
+            snippet(0.0, 1.0, "こんにちは 世界"),
+            snippet(1.0, 1.0, "🎉🎉 パーティー"),
+            snippet(2.0, 1.0, "終わり 🙂"),
+        ];
+        let chunks = to_chunks(&snippets, 10, 4);
+        let texts: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+
+        // Lengths are counted in chars, not bytes: the 8-char first snippet
+        // (22 bytes) fits in 10, and its last word carries into the next
+        // chunk. A 5-char word can't fit a 4-char overlap, so none carries
+        assert_eq!(
+            texts,
+            ["こんにちは 世界", "世界 🎉🎉 パーティー", "終わり 🙂"]
+        );
+        assert_eq!(overlap_tail("こんにちは 世界", 2), "世界");
+        assert_eq!(overlap_tail("こんにちは 世界", 1), "");
+        assert_eq!(overlap_tail("絵文字 🎉🎉🎉", 3), "🎉🎉🎉");
+    }
+
+    #[test]
+    fn multibyte_text_survives_the_other_text_options() {
+        assert_eq!(csv_escape("日本語, 🎉"), "\"日本語, 🎉\"");
+        assert_eq!(csv_escape("\"引用\""), "\"\"\"引用\"\"\"");
+        // Only ASCII is title-cased; the point is not slicing inside "é"
+        assert_eq!(normalize_language("en-éab"), "en-éab");
+        assert_eq!(decode_html_entities("&日本;&#127881;"), "&日本;🎉");
+        assert!(matches_query("東京 タワー", "タワー", true));
+        assert!(!matches_query("東京タワー", "タワー", true));
+
+        let mut snippets = vec![snippet(0.0, 1.0, "首都 【東京】 [音楽] 🎉")];
+        assert!(rewrite_text(&mut snippets, strip_bracketed));
+        assert_eq!(snippets[0].text, "首都 【東京】 🎉");
+
+        // Preview cuts by time between whole snippets, never inside text
+        let mut preview = response(
+            "dQw4w9WgXcQ",
+            vec![snippet(0.0, 1.0, "🎉 はじめ"), snippet(5.0, 1.0, "あと")],
+        );
+        let payload = YTRequest {
+            preview_seconds: Some(2.0),
+            ..Default::default()
+        };
+        apply_transcript_options(&test_state(), &payload, &mut preview);
+        assert_eq!(preview.transcript.len(), 1);
+        assert_eq!(preview.transcript[0].text, "🎉 はじめ");
+    }
+
+    fn test_state() -> AppState {
+        AppState {
+            // Explicit rather than from_env, so CACHE_TTL_SECS or PROXY_URLS on
+            // the host can't change what the tests see
+            cache: TranscriptCache::new(Duration::from_secs(300), 16),
+            proxies: ProxyPool::new(Vec::new(), None, Duration::ZERO)
+                .unwrap_or_else(|e| panic!("{}", e.message)),
+            rate_limiter: None,
+            api_keys: Vec::new(),
+            profanity: Vec::new(),
+            default_languages: vec!["en".to_string()],
+            allowed_formats: OutputFormat::ALL.to_vec(),
+            max_video_duration: None,
+            metrics: PrometheusBuilder::new().build_recorder().handle(),
+            in_flight: InFlight::default(),
+            ready: AtomicBool::new(true),
+        }
+    }
+
+    fn response(video_id: &str, transcript: Vec<TranscriptSnippet>) -> YTResponse {
+        YTResponse {
+            id: video_id.to_string(),
+            input: None,
+            canonical_url: None,
+            title: "Title".to_string(),
+            author: "Author".to_string(),
+            views: "1".to_string(),
+            view_count: Some(1),
+            published_at: None,
+            duration_seconds: None,
+            duration: None,
+            start_seconds: None,
+            thumbnail_url: default_thumbnail_url(video_id),
+            thumbnails: Vec::new(),
+            language: "en".to_string(),
+            is_translated: false,
+            generated: false,
+            quality_hint: QualityHint::new(false, false),
+            full_text: None,
+            chapters: None,
+            stats: None,
+            detected_language: None,
+            detected_language_confidence: None,
+            sounds: None,
+            transformations: Vec::new(),
+            details_unavailable: false,
+            total: None,
+            has_more: None,
+            truncated: None,
+            total_matches: None,
+            transcript,
+        }
+    }
+
+    // Caches `response` under the key a default-language request looks up
+    fn cache(state: &AppState, response: YTResponse) {
+        let key = CacheKey {
+            video_id: response.id.clone(),
+            languages: state.default_languages.clone(),
+            translate_to: None,
+            prefer_manual: true,
+        };
+        state.cache.insert(key, response);
+    }
+
+    #[tokio::test]
+    async fn cached_long_videos_still_respect_the_duration_limit() {
+        let state = AppState {
+            max_video_duration: Some(3600),
+            ..test_state()
+        };
+        cache(
+            &state,
+            YTResponse {
+                duration_seconds: Some(7200),
+                ..response("dQw4w9WgXcQ", vec![snippet(0.0, 1.0, "hi")])
+            },
+        );
+        let mut options = FetchOptions {
+            languages: state.default_languages.clone(),
+            translate_to: None,
+            prefer_manual: true,
+            use_proxy: false,
+            max_duration: state.max_duration(false),
+            require_details: true,
+        };
+
+        let err = fetch_video_cached(&state, "dQw4w9WgXcQ".to_string(), &options)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        options.max_duration = state.max_duration(true);
+        let (_, hit) = fetch_video_cached(&state, "dQw4w9WgXcQ".to_string(), &options)
+            .await
+            .ok()
+            .unwrap();
+        assert!(hit);
+    }
+
+    #[tokio::test]
+    async fn metrics_require_an_api_key() {
+        use tower::ServiceExt;
+
+        let router = app(Arc::new(AppState {
+            api_keys: vec!["secret".to_string()],
+            ..test_state()
+        }));
+        let get = |key: Option<&str>| {
+            let mut request = Request::builder().uri("/metrics");
+            if let Some(key) = key {
+                request = request.header("x-api-key", key);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+
+        let res = router.clone().oneshot(get(None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let res = router.oneshot(get(Some("secret"))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn sentences_are_only_reported_when_regrouping_changes_something() {
+        let state = test_state();
+        let payload = YTRequest {
+            sentences: true,
+            ..Default::default()
+        };
+
+        let mut whole = response("dQw4w9WgXcQ", vec![snippet(0.0, 1.0, "One. Two.")]);
+        apply_transcript_options(&state, &payload, &mut whole);
+        assert_eq!(whole.transformations, ["sentences"]);
+
+        let mut split = response(
+            "dQw4w9WgXcQ",
+            vec![snippet(0.0, 1.0, "One."), snippet(1.0, 1.0, "Two.")],
+        );
+        apply_transcript_options(&state, &payload, &mut split);
+        assert!(split.transformations.is_empty());
+    }
+
+    #[tokio::test]
+    async fn batch_items_report_their_transformations() {
+        use tower::ServiceExt;
+
+        let state = test_state();
+        let marked = to_snippet(0, &part(0.0, 1.0, ">> it&#39;s me"));
+        cache(&state, response("dQw4w9WgXcQ", vec![marked]));
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/transcript/batch")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"video_ids":["dQw4w9WgXcQ"]}"#))
+            .unwrap();
+        let res = app(Arc::new(state)).oneshot(request).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let items: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let result = &items[0]["result"];
+        assert_eq!(result["transformations"], json!(["strip_prefix", "clean"]));
+        assert_eq!(result["transcript"][0]["text"], "it's me");
+    }
+
+    #[test]
+    fn search_context_keeps_only_overlapping_snippets() {
+        let state = test_state();
+        let transcript = vec![
+            snippet(0.0, 1.0, "before"),
+            snippet(1.0, 1.0, "the match"),
+            snippet(2.0, 1.0, "after"),
+            snippet(3.5, 1.0, "later"),
+        ];
+        let search = |context: f64| {
+            let payload = YTRequest {
+                search: Some("match".to_string()),
+                context_seconds: Some(context),
+                ..Default::default()
+            };
+            let mut response = response("dQw4w9WgXcQ", transcript.clone());
+            apply_transcript_options(&state, &payload, &mut response);
+            response
+                .transcript
+                .into_iter()
+                .map(|s| s.text)
+                .collect::<Vec<_>>()
+        };
+
+        // Neighbours that only touch the match at 1.0 and 2.0 aren't context
+        assert_eq!(search(0.0), ["the match"]);
+        assert_eq!(search(0.5), ["before", "the match", "after"]);
+        assert_eq!(search(2.0), ["before", "the match", "after", "later"]);
+
+        assert_eq!(
+            merge_windows(&[(0.0, 1.0), (1.0, 2.0)], 0.0),
+            [(0.0, 1.0), (1.0, 2.0)]
+        );
+        assert_eq!(
+            merge_windows(&[(0.0, 1.0), (2.0, 3.0)], 0.75),
+            [(0.0, 3.75)]
+        );
+    }
+
+    #[tokio::test]
+    async fn multi_ignores_accept_negotiated_formats() {
+        use tower::ServiceExt;
+
+        let state = test_state();
+        cache(
+            &state,
+            response("dQw4w9WgXcQ", vec![snippet(0.0, 1.0, "hi")]),
+        );
+        let router = app(Arc::new(state));
+        let post = |body: &'static str| {
+            Request::builder()
+                .method(Method::POST)
+                .uri("/transcript")
+                .header(header::CONTENT_TYPE, "application/json")
+                .header(header::ACCEPT, "text/csv")
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let res = router
+            .clone()
+            .oneshot(post(
+                r#"{"video_id":"dQw4w9WgXcQ","multi":true,"languages":["en"]}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], JSON_CONTENT_TYPE);
+
+        let res = router
+            .oneshot(post(
+                r#"{"video_id":"dQw4w9WgXcQ","multi":true,"languages":["en"],"format":"csv"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn chunked_speaker_and_multi_responses_report_transformations() {
+        let state = test_state();
+        let marked = || vec![to_snippet(0, &part(0.0, 1.0, ">> it&#39;s me"))];
+        let json = |(_, body): (&str, String)| -> serde_json::Value {
+            serde_json::from_str(&body).unwrap()
+        };
+        let expected = json!(["strip_prefix", "clean"]);
+
+        for payload in [
+            YTRequest {
+                chunk: Some(ChunkOptions {
+                    max_chars: 100,
+                    overlap: 0,
+                }),
+                ..Default::default()
+            },
+            YTRequest {
+                group_speakers: true,
+                ..Default::default()
+            },
+        ] {
+            let mut response = response("dQw4w9WgXcQ", marked());
+            apply_transcript_options(&state, &payload, &mut response);
+            assert_eq!(
+                json(render_transcript(&payload, response))["transformations"],
+                expected
+            );
+        }
+
+        cache(&state, response("dQw4w9WgXcQ", marked()));
+        let payload = YTRequest {
+            video_id: Some("dQw4w9WgXcQ".to_string()),
+            multi: true,
+            languages: Some(vec!["en".to_string()]),
+            ..Default::default()
+        };
+        let (rendered, _) = multi_transcript(&state, &payload).await.ok().unwrap();
+        assert_eq!(json(rendered)["transformations"]["en"], expected);
+    }
+
+    #[tokio::test]
+    async fn large_transcripts_are_compressed() {
+        use tower::ServiceExt;
+
+        let state = test_state();
+        let transcript = (0..500)
+            .map(|i| snippet(i as f64, 1.0, "never gonna give you up"))
+            .collect();
+        cache(&state, response("dQw4w9WgXcQ", transcript));
+
+        let request = Request::builder()
+            .uri("/transcript/dQw4w9WgXcQ")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let res = app(Arc::new(state)).oneshot(request).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+    }
 }